@@ -0,0 +1,195 @@
+use crate::{NativePlatform, Win32Platform};
+use imgui::{internal::RawCast, ClipboardBackend, Context};
+use std::{
+    ffi::{c_char, c_void},
+    rc::Rc,
+    time::Duration,
+};
+use windows::Win32::Foundation::HWND;
+
+// Other applications hold the clipboard open for short moments, e.g. clipboard managers reading
+// what was just copied
+const OPEN_ATTEMPTS: u32 = 5;
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(5);
+
+/// `ClipboardBackend` over the Windows clipboard, in `CF_UNICODETEXT`.
+///
+/// `Win32Impl::init` installs one for its window, `Win32Impl::set_clipboard_enabled` takes it out.
+pub struct Win32Clipboard<P: Win32Platform = NativePlatform> {
+    platform: Rc<P>,
+    hwnd: HWND,
+}
+
+impl Win32Clipboard {
+    /// A clipboard opened on behalf of `hwnd`.
+    pub fn new(hwnd: HWND) -> Self {
+        Self::with_platform(Rc::new(NativePlatform), hwnd)
+    }
+}
+
+impl<P: Win32Platform> Win32Clipboard<P> {
+    pub(crate) fn with_platform(platform: Rc<P>, hwnd: HWND) -> Self {
+        Self { platform, hwnd }
+    }
+
+    /// Runs `f` with the clipboard open, `None` if it stayed busy.
+    fn with_clipboard<T>(&self, f: impl FnOnce(&P) -> Option<T>) -> Option<T> {
+        for attempt in 0..OPEN_ATTEMPTS {
+            if attempt > 0 {
+                std::thread::sleep(OPEN_RETRY_DELAY);
+            }
+            if self.platform.open_clipboard(self.hwnd) {
+                let result = f(&self.platform);
+                self.platform.close_clipboard();
+                return result;
+            }
+        }
+        None
+    }
+}
+
+impl<P: Win32Platform> ClipboardBackend for Win32Clipboard<P> {
+    fn get(&mut self) -> Option<String> {
+        self.with_clipboard(|platform| platform.clipboard_text())
+            .map(|text| String::from_utf16_lossy(&text))
+    }
+
+    fn set(&mut self, value: &str) {
+        let text: Vec<u16> = value.encode_utf16().chain([0]).collect();
+        self.with_clipboard(|platform| platform.set_clipboard_text(&text).then_some(()));
+    }
+}
+
+/// imgui's own clipboard callbacks, put back when `Win32Clipboard` is taken out again.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DefaultClipboard {
+    get_text: Option<unsafe extern "C" fn(*mut c_void) -> *const c_char>,
+    set_text: Option<unsafe extern "C" fn(*mut c_void, *const c_char)>,
+}
+
+impl DefaultClipboard {
+    /// The callbacks `imgui` uses now, `None` once a `ClipboardBackend` replaced imgui's own. That
+    /// backend goes away with the next one installed, so its callbacks can't be put back.
+    pub(crate) fn current(imgui: &mut Context) -> Option<Self> {
+        let io = unsafe { imgui.io_mut().raw_mut() };
+        io.ClipboardUserData.is_null().then_some(Self {
+            get_text: io.GetClipboardTextFn,
+            set_text: io.SetClipboardTextFn,
+        })
+    }
+
+    pub(crate) fn restore(self, imgui: &mut Context) {
+        let io = unsafe { imgui.io_mut().raw_mut() };
+        io.GetClipboardTextFn = self.get_text;
+        io.SetClipboardTextFn = self.set_text;
+        io.ClipboardUserData = std::ptr::null_mut();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockPlatform;
+    use crate::{test_util::*, Win32Impl};
+
+    const HWND_MAIN: HWND = HWND(0x1000);
+
+    fn clipboard() -> Win32Clipboard<MockPlatform> {
+        Win32Clipboard::with_platform(Rc::new(MockPlatform::new()), HWND_MAIN)
+    }
+
+    #[test]
+    fn text_round_trips_through_the_clipboard() {
+        let mut clipboard = clipboard();
+        assert_eq!(clipboard.get(), None);
+
+        clipboard.set("héllo 🌍");
+        assert_eq!(
+            clipboard.platform.clipboard_contents().as_deref(),
+            Some("héllo 🌍")
+        );
+        assert_eq!(clipboard.get().as_deref(), Some("héllo 🌍"));
+        assert_eq!(clipboard.platform.last_clipboard_owner(), Some(HWND_MAIN));
+        // Closed again after every use
+        assert_eq!(clipboard.platform.clipboard_owner(), None);
+    }
+
+    #[test]
+    fn busy_clipboard_is_retried() {
+        let mut clipboard = clipboard();
+        clipboard.platform.put_clipboard("copied");
+
+        clipboard.platform.set_clipboard_busy(OPEN_ATTEMPTS - 1);
+        assert_eq!(clipboard.get().as_deref(), Some("copied"));
+
+        clipboard.platform.set_clipboard_busy(OPEN_ATTEMPTS);
+        assert_eq!(clipboard.get(), None);
+        clipboard.platform.set_clipboard_busy(OPEN_ATTEMPTS);
+        clipboard.set("lost");
+        assert_eq!(
+            clipboard.platform.clipboard_contents().as_deref(),
+            Some("copied")
+        );
+    }
+
+    #[test]
+    fn init_installs_the_clipboard() {
+        let (_guard, mut ctx, backend) = setup();
+        backend.platform().put_clipboard("pasted");
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.clipboard_text().as_deref(), Some("pasted"));
+        ui.set_clipboard_text("copied");
+        assert_eq!(
+            backend.platform().clipboard_contents().as_deref(),
+            Some("copied")
+        );
+    }
+
+    #[test]
+    fn disabled_clipboard_falls_back_to_imgui() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.platform().put_clipboard("system");
+
+        backend.set_clipboard_enabled(&mut ctx, false);
+        assert!(!backend.clipboard_enabled());
+        let ui = ctx.new_frame();
+        ui.set_clipboard_text("copied");
+        assert_eq!(ui.clipboard_text().as_deref(), Some("copied"));
+        assert_eq!(
+            backend.platform().clipboard_contents().as_deref(),
+            Some("system")
+        );
+        ctx.render();
+
+        backend.set_clipboard_enabled(&mut ctx, true);
+        assert_eq!(ctx.new_frame().clipboard_text().as_deref(), Some("system"));
+    }
+
+    #[test]
+    fn application_clipboard_is_kept() {
+        struct Fixed;
+        impl imgui::ClipboardBackend for Fixed {
+            fn get(&mut self) -> Option<String> {
+                Some("fixed".into())
+            }
+            fn set(&mut self, _value: &str) {}
+        }
+
+        let (_guard, mut ctx) = context();
+        ctx.set_clipboard_backend(Fixed);
+        let mut backend =
+            Win32Impl::init_with_platform(&mut ctx, HWND_MAIN, mock_platform()).unwrap();
+        backend.platform().put_clipboard("system");
+
+        assert!(!backend.clipboard_enabled());
+        assert_eq!(ctx.new_frame().clipboard_text().as_deref(), Some("fixed"));
+        ctx.render();
+
+        // Replacing it would drop it for good
+        backend.set_clipboard_enabled(&mut ctx, true);
+        assert!(!backend.clipboard_enabled());
+        backend.set_clipboard_enabled(&mut ctx, false);
+        assert_eq!(ctx.new_frame().clipboard_text().as_deref(), Some("fixed"));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{Win32ImplError, Win32Platform};
+use imgui::{
+    sys::{ImGuiMouseCursor, ImGuiMouseCursor_None},
+    MouseCursor,
+};
+use std::{collections::HashMap, fmt, path::Path};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::BOOL,
+        Graphics::Gdi::{CreateBitmap, DeleteObject},
+        UI::WindowsAndMessaging::*,
+    },
+};
+
+/// Picks the cursor for the one imgui asks for ahead of `CursorMap`'s own entries. Returning `Some`
+/// shows that cursor instead, e.g. for a `RequestedCursor::Custom` id of the application's.
+pub type CursorHook = Box<dyn FnMut(RequestedCursor) -> Option<HCURSOR>>;
+
+/// The mouse cursor imgui asks for in a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestedCursor {
+    /// imgui hides the cursor, `ImGuiMouseCursor_None` or `io.mouse_draw_cursor`.
+    Hidden,
+    Imgui(MouseCursor),
+    /// An id past imgui's own cursors, set by the application through `igSetMouseCursor`. Shown as
+    /// the arrow unless the hook picks a cursor for it.
+    Custom(ImGuiMouseCursor),
+}
+
+impl RequestedCursor {
+    pub fn from_raw(mouse_cursor: ImGuiMouseCursor) -> Self {
+        if mouse_cursor == ImGuiMouseCursor_None {
+            return RequestedCursor::Hidden;
+        }
+        MouseCursor::VARIANTS
+            .into_iter()
+            .find(|&cursor| cursor as ImGuiMouseCursor == mouse_cursor)
+            .map_or(
+                RequestedCursor::Custom(mouse_cursor),
+                RequestedCursor::Imgui,
+            )
+    }
+}
+
+/// The cursors shown for imgui's mouse cursors.
+///
+/// Cursors without an entry get the matching system `IDC_*` cursor. The map doesn't own the
+/// handles, cursors from `load_cursor_file` and `create_cursor_rgba` are destroyed by the
+/// application once no longer mapped.
+#[derive(Default)]
+pub struct CursorMap {
+    cursors: HashMap<MouseCursor, HCURSOR>,
+    hook: Option<CursorHook>,
+}
+
+impl fmt::Debug for CursorMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CursorMap")
+            .field("cursors", &self.cursors)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+impl CursorMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows `cursor` for imgui's `mouse_cursor` instead of the system cursor.
+    pub fn set(&mut self, mouse_cursor: MouseCursor, cursor: HCURSOR) {
+        self.cursors.insert(mouse_cursor, cursor);
+    }
+
+    /// Goes back to the system cursor for `mouse_cursor`.
+    pub fn remove(&mut self, mouse_cursor: MouseCursor) -> Option<HCURSOR> {
+        self.cursors.remove(&mouse_cursor)
+    }
+
+    pub fn get(&self, mouse_cursor: MouseCursor) -> Option<HCURSOR> {
+        self.cursors.get(&mouse_cursor).copied()
+    }
+
+    pub fn set_hook<F: FnMut(RequestedCursor) -> Option<HCURSOR> + 'static>(&mut self, hook: F) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_hook(&mut self) {
+        self.hook = None;
+    }
+
+    /// The cursor to show for the one imgui asks for, a null handle hides it.
+    pub(crate) fn resolve<P: Win32Platform>(
+        &mut self,
+        platform: &P,
+        requested: RequestedCursor,
+    ) -> HCURSOR {
+        if let Some(cursor) = self.hook.as_mut().and_then(|hook| hook(requested)) {
+            return cursor;
+        }
+
+        let mouse_cursor = match requested {
+            RequestedCursor::Hidden => return HCURSOR(0),
+            RequestedCursor::Imgui(mouse_cursor) => mouse_cursor,
+            RequestedCursor::Custom(_) => MouseCursor::Arrow,
+        };
+        match self.get(mouse_cursor) {
+            Some(cursor) => cursor,
+            None => platform.load_cursor(system_cursor(mouse_cursor)),
+        }
+    }
+}
+
+fn system_cursor(mouse_cursor: MouseCursor) -> PCWSTR {
+    match mouse_cursor {
+        MouseCursor::Arrow => IDC_ARROW,
+        MouseCursor::TextInput => IDC_IBEAM,
+        MouseCursor::ResizeAll => IDC_SIZEALL,
+        MouseCursor::ResizeEW => IDC_SIZEWE,
+        MouseCursor::ResizeNS => IDC_SIZENS,
+        MouseCursor::ResizeNESW => IDC_SIZENESW,
+        MouseCursor::ResizeNWSE => IDC_SIZENWSE,
+        MouseCursor::Hand => IDC_HAND,
+        MouseCursor::NotAllowed => IDC_NO,
+    }
+}
+
+/// Loads a `.cur` or animated `.ani` cursor file.
+pub fn load_cursor_file(path: &Path) -> Result<HCURSOR, Win32ImplError> {
+    #[cfg(windows)]
+    let path: Vec<u16> = {
+        use std::os::windows::ffi::OsStrExt;
+        path.as_os_str().encode_wide().chain([0]).collect()
+    };
+    #[cfg(not(windows))]
+    let path: Vec<u16> = path.to_string_lossy().encode_utf16().chain([0]).collect();
+
+    unsafe { LoadCursorFromFileW(PCWSTR(path.as_ptr())) }.map_err(|e| {
+        Win32ImplError::ExternalError(format!("LoadCursorFromFileW failed with `{e}`"))
+    })
+}
+
+/// Makes a cursor out of `width` x `height` RGBA pixels with straight alpha, row by row from the
+/// top, clicking at `hotspot`.
+pub fn create_cursor_rgba(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    hotspot: [u32; 2],
+) -> Result<HCURSOR, Win32ImplError> {
+    if rgba.len() != width as usize * height as usize * 4 {
+        return Err(Win32ImplError::ExternalError(format!(
+            "{} bytes of pixels for a {width}x{height} cursor",
+            rgba.len()
+        )));
+    }
+
+    let bgra = rgba_to_bgra(rgba);
+    // The color bitmap's alpha decides transparency, the AND mask only has to be clear. Its rows
+    // are padded to 16 bits.
+    let mask = vec![0u8; (width as usize).div_ceil(16) * 2 * height as usize];
+    unsafe {
+        let color = CreateBitmap(
+            width as i32,
+            height as i32,
+            1,
+            32,
+            Some(bgra.as_ptr().cast()),
+        );
+        let mask = CreateBitmap(
+            width as i32,
+            height as i32,
+            1,
+            1,
+            Some(mask.as_ptr().cast()),
+        );
+        let info = ICONINFO {
+            fIcon: BOOL::from(false),
+            xHotspot: hotspot[0],
+            yHotspot: hotspot[1],
+            hbmMask: mask,
+            hbmColor: color,
+        };
+        let icon = CreateIconIndirect(&info);
+        DeleteObject(color);
+        DeleteObject(mask);
+        icon.map(|icon| HCURSOR(icon.0)).map_err(|e| {
+            Win32ImplError::ExternalError(format!("CreateIconIndirect failed with `{e}`"))
+        })
+    }
+}
+
+// 32-bit GDI bitmaps are laid out blue first
+fn rgba_to_bgra(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::*, MockPlatform, ProcResponse};
+    use imgui::sys::{igSetMouseCursor, ImGuiMouseCursor_COUNT};
+    use windows::Win32::{
+        Foundation::LRESULT,
+        UI::WindowsAndMessaging::{HTCLIENT, IDC_ARROW, WM_SETCURSOR},
+    };
+
+    #[test]
+    fn mapped_cursors_replace_system_cursors() {
+        let platform = MockPlatform::new();
+        let mut cursors = CursorMap::new();
+        cursors.set(MouseCursor::Hand, HCURSOR(0x77));
+
+        let resolve = |cursors: &mut CursorMap, cursor| {
+            cursors.resolve(&platform, RequestedCursor::Imgui(cursor))
+        };
+        assert_eq!(resolve(&mut cursors, MouseCursor::Hand), HCURSOR(0x77));
+        assert_eq!(
+            resolve(&mut cursors, MouseCursor::TextInput),
+            HCURSOR(IDC_IBEAM.0 as isize)
+        );
+        assert_eq!(
+            cursors.resolve(&platform, RequestedCursor::Hidden),
+            HCURSOR(0)
+        );
+
+        cursors.remove(MouseCursor::Hand);
+        assert_eq!(
+            resolve(&mut cursors, MouseCursor::Hand),
+            HCURSOR(IDC_HAND.0 as isize)
+        );
+    }
+
+    #[test]
+    fn hook_comes_first() {
+        let platform = MockPlatform::new();
+        let mut cursors = CursorMap::new();
+        cursors.set(MouseCursor::Arrow, HCURSOR(0x77));
+        cursors.set_hook(|cursor| match cursor {
+            RequestedCursor::Imgui(MouseCursor::Arrow) | RequestedCursor::Hidden => {
+                Some(HCURSOR(0x88))
+            }
+            _ => None,
+        });
+
+        assert_eq!(
+            cursors.resolve(&platform, RequestedCursor::Imgui(MouseCursor::Arrow)),
+            HCURSOR(0x88)
+        );
+        assert_eq!(
+            cursors.resolve(&platform, RequestedCursor::Hidden),
+            HCURSOR(0x88)
+        );
+        assert_eq!(
+            cursors.resolve(&platform, RequestedCursor::Imgui(MouseCursor::ResizeNS)),
+            HCURSOR(IDC_SIZENS.0 as isize)
+        );
+    }
+
+    const CUSTOM: ImGuiMouseCursor = ImGuiMouseCursor_COUNT + 2;
+
+    #[test]
+    fn custom_cursors_reach_the_hook_and_fall_back_to_the_arrow() {
+        let platform = MockPlatform::new();
+        let mut cursors = CursorMap::new();
+        let custom = RequestedCursor::from_raw(CUSTOM);
+        assert_eq!(custom, RequestedCursor::Custom(CUSTOM));
+
+        assert_eq!(
+            cursors.resolve(&platform, custom),
+            HCURSOR(IDC_ARROW.0 as isize)
+        );
+
+        cursors.set_hook(move |cursor| match cursor {
+            RequestedCursor::Custom(id) if id == CUSTOM => Some(HCURSOR(0x99)),
+            _ => None,
+        });
+        assert_eq!(cursors.resolve(&platform, custom), HCURSOR(0x99));
+        assert_eq!(
+            cursors.resolve(&platform, RequestedCursor::Hidden),
+            HCURSOR(0)
+        );
+    }
+
+    #[test]
+    fn raw_cursors_convert() {
+        for cursor in MouseCursor::VARIANTS {
+            assert_eq!(
+                RequestedCursor::from_raw(cursor as ImGuiMouseCursor),
+                RequestedCursor::Imgui(cursor)
+            );
+        }
+        assert_eq!(RequestedCursor::from_raw(-1), RequestedCursor::Hidden);
+    }
+
+    #[test]
+    fn pixels_are_swizzled_for_gdi() {
+        assert_eq!(
+            rgba_to_bgra(&[1, 2, 3, 4, 5, 6, 7, 8]),
+            [3, 2, 1, 4, 7, 6, 5, 8]
+        );
+    }
+
+    #[test]
+    fn prepare_frame_sets_system_cursor() {
+        let (_guard, mut ctx, mut backend) = setup();
+
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        assert_eq!(
+            backend.platform().current_cursor(),
+            HCURSOR(IDC_ARROW.0 as isize)
+        );
+    }
+
+    #[test]
+    fn cursor_map_applies_to_frames_and_wm_setcursor() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let mut cursors = CursorMap::new();
+        cursors.set(imgui::MouseCursor::Arrow, HCURSOR(0x77));
+        backend.set_cursor_map(cursors);
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(backend.platform().current_cursor(), HCURSOR(0x77));
+
+        backend.cursor_map_mut().set_hook(|_| Some(HCURSOR(0x88)));
+        let response = send(backend.platform(), WM_SETCURSOR, 0, HTCLIENT as isize);
+        assert_eq!(response, ProcResponse::Handled(LRESULT(1)));
+        assert_eq!(backend.platform().current_cursor(), HCURSOR(0x88));
+    }
+
+    #[test]
+    fn custom_cursor_ids_show_the_arrow() {
+        let (_guard, mut ctx, mut backend) = setup();
+
+        ctx.new_frame();
+        unsafe { igSetMouseCursor(CUSTOM) };
+        ctx.render();
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        assert_eq!(
+            backend.platform().current_cursor(),
+            HCURSOR(IDC_ARROW.0 as isize)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use imgui::{BackendFlags, ConfigFlags, Io, Key};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+use windows::Win32::{
+    Foundation::ERROR_SUCCESS,
+    UI::Input::XboxController::{
+        XInputGetCapabilities, XInputGetState, XINPUT_CAPABILITIES, XINPUT_FLAG_GAMEPAD,
+        XINPUT_GAMEPAD, XINPUT_GAMEPAD_A, XINPUT_GAMEPAD_B, XINPUT_GAMEPAD_BACK,
+        XINPUT_GAMEPAD_BUTTON_FLAGS, XINPUT_GAMEPAD_DPAD_DOWN, XINPUT_GAMEPAD_DPAD_LEFT,
+        XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP, XINPUT_GAMEPAD_LEFT_SHOULDER,
+        XINPUT_GAMEPAD_LEFT_THUMB, XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE,
+        XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_RIGHT_THUMB,
+        XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE, XINPUT_GAMEPAD_START,
+        XINPUT_GAMEPAD_TRIGGER_THRESHOLD, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y, XINPUT_STATE,
+        XUSER_MAX_COUNT,
+    },
+};
+
+// Analog inputs past this fraction of their range past the dead zone count as pressed
+const ANALOG_PRESS_THRESHOLD: f32 = 0.10;
+
+/// The XInput calls used for gamepad navigation.
+///
+/// `NativeXInput` forwards to the system, `MockXInput` serves fake controller states.
+pub trait XInput {
+    /// Whether a gamepad is plugged in as user `user_index`, from `0` to `XUSER_MAX_COUNT`.
+    fn is_connected(&self, user_index: u32) -> bool;
+    /// Current state of the gamepad of user `user_index`, `None` once it's unplugged.
+    fn gamepad(&self, user_index: u32) -> Option<XINPUT_GAMEPAD>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeXInput;
+
+impl XInput for NativeXInput {
+    fn is_connected(&self, user_index: u32) -> bool {
+        let mut capabilities = XINPUT_CAPABILITIES::default();
+        let result =
+            unsafe { XInputGetCapabilities(user_index, XINPUT_FLAG_GAMEPAD, &mut capabilities) };
+        result == ERROR_SUCCESS.0
+    }
+
+    fn gamepad(&self, user_index: u32) -> Option<XINPUT_GAMEPAD> {
+        let mut state = XINPUT_STATE::default();
+        let result = unsafe { XInputGetState(user_index, &mut state) };
+        (result == ERROR_SUCCESS.0).then_some(state.Gamepad)
+    }
+}
+
+/// In-memory controllers.
+///
+/// Clones share their controllers, keep one to plug, unplug and move gamepads after handing
+/// another to `Win32Impl::enable_gamepad_with`.
+#[derive(Debug, Default, Clone)]
+pub struct MockXInput {
+    gamepads: Rc<RefCell<[Option<XINPUT_GAMEPAD>; XUSER_MAX_COUNT as usize]>>,
+    probes: Rc<Cell<u32>>,
+}
+
+impl MockXInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plugs in or updates the gamepad of user `user_index`.
+    pub fn set_gamepad(&self, user_index: u32, gamepad: XINPUT_GAMEPAD) {
+        self.gamepads.borrow_mut()[user_index as usize] = Some(gamepad);
+    }
+
+    pub fn unplug(&self, user_index: u32) {
+        self.gamepads.borrow_mut()[user_index as usize] = None;
+    }
+
+    /// How many times `is_connected` was called.
+    pub fn probe_count(&self) -> u32 {
+        self.probes.get()
+    }
+}
+
+impl XInput for MockXInput {
+    fn is_connected(&self, user_index: u32) -> bool {
+        self.probes.set(self.probes.get() + 1);
+        self.gamepad(user_index).is_some()
+    }
+
+    fn gamepad(&self, user_index: u32) -> Option<XINPUT_GAMEPAD> {
+        self.gamepads
+            .borrow()
+            .get(user_index as usize)
+            .copied()
+            .flatten()
+    }
+}
+
+pub(crate) struct Gamepad {
+    xinput: Box<dyn XInput>,
+    user_index: Option<u32>,
+    want_probe: bool,
+}
+
+impl Gamepad {
+    pub(crate) fn new(xinput: Box<dyn XInput>) -> Self {
+        Self {
+            xinput,
+            user_index: None,
+            want_probe: true,
+        }
+    }
+
+    /// Looks for a connected controller again on the next update. Probing a user without a
+    /// controller is slow, so it's only done when `WM_DEVICECHANGE` says devices changed.
+    pub(crate) fn request_probe(&mut self) {
+        self.want_probe = true;
+    }
+
+    pub(crate) fn update(&mut self, io: &mut Io) {
+        if !io.config_flags.contains(ConfigFlags::NAV_ENABLE_GAMEPAD) {
+            return;
+        }
+
+        if self.want_probe {
+            self.want_probe = false;
+            self.user_index = (0..XUSER_MAX_COUNT).find(|&i| self.xinput.is_connected(i));
+        }
+
+        io.backend_flags.remove(BackendFlags::HAS_GAMEPAD);
+        let gamepad = match self.user_index.and_then(|i| self.xinput.gamepad(i)) {
+            Some(gamepad) => gamepad,
+            None => return,
+        };
+        io.backend_flags.insert(BackendFlags::HAS_GAMEPAD);
+
+        map_gamepad(io, &gamepad);
+    }
+}
+
+/// Where `value` sits between the end of the dead zone `v0` and the end of the range `v1`, from
+/// `0.0` to `1.0`.
+fn analog(value: f32, v0: f32, v1: f32) -> f32 {
+    ((value - v0) / (v1 - v0)).clamp(0.0, 1.0)
+}
+
+fn map_gamepad(io: &mut Io, gamepad: &XINPUT_GAMEPAD) {
+    let buttons = [
+        (Key::GamepadStart, XINPUT_GAMEPAD_START),
+        (Key::GamepadBack, XINPUT_GAMEPAD_BACK),
+        (Key::GamepadFaceLeft, XINPUT_GAMEPAD_X),
+        (Key::GamepadFaceRight, XINPUT_GAMEPAD_B),
+        (Key::GamepadFaceUp, XINPUT_GAMEPAD_Y),
+        (Key::GamepadFaceDown, XINPUT_GAMEPAD_A),
+        (Key::GamepadDpadLeft, XINPUT_GAMEPAD_DPAD_LEFT),
+        (Key::GamepadDpadRight, XINPUT_GAMEPAD_DPAD_RIGHT),
+        (Key::GamepadDpadUp, XINPUT_GAMEPAD_DPAD_UP),
+        (Key::GamepadDpadDown, XINPUT_GAMEPAD_DPAD_DOWN),
+        (Key::GamepadL1, XINPUT_GAMEPAD_LEFT_SHOULDER),
+        (Key::GamepadR1, XINPUT_GAMEPAD_RIGHT_SHOULDER),
+        (Key::GamepadL3, XINPUT_GAMEPAD_LEFT_THUMB),
+        (Key::GamepadR3, XINPUT_GAMEPAD_RIGHT_THUMB),
+    ];
+    for (key, XINPUT_GAMEPAD_BUTTON_FLAGS(button)) in buttons {
+        io.add_key_event(key, gamepad.wButtons.0 & button != 0);
+    }
+
+    // The dead zone constants share the button flags type in the bindings
+    let trigger = XINPUT_GAMEPAD_TRIGGER_THRESHOLD.0 as f32;
+    let left = XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE.0 as f32;
+    let right = XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE.0 as f32;
+    let (lx, ly) = (gamepad.sThumbLX as f32, gamepad.sThumbLY as f32);
+    let (rx, ry) = (gamepad.sThumbRX as f32, gamepad.sThumbRY as f32);
+
+    let analogs = [
+        (
+            Key::GamepadL2,
+            analog(gamepad.bLeftTrigger as f32, trigger, 255.0),
+        ),
+        (
+            Key::GamepadR2,
+            analog(gamepad.bRightTrigger as f32, trigger, 255.0),
+        ),
+        (Key::GamepadLStickLeft, analog(lx, -left, -32768.0)),
+        (Key::GamepadLStickRight, analog(lx, left, 32767.0)),
+        (Key::GamepadLStickUp, analog(ly, left, 32767.0)),
+        (Key::GamepadLStickDown, analog(ly, -left, -32768.0)),
+        (Key::GamepadRStickLeft, analog(rx, -right, -32768.0)),
+        (Key::GamepadRStickRight, analog(rx, right, 32767.0)),
+        (Key::GamepadRStickUp, analog(ry, right, 32767.0)),
+        (Key::GamepadRStickDown, analog(ry, -right, -32768.0)),
+    ];
+    for (key, value) in analogs {
+        io.add_key_analog_event(key, value > ANALOG_PRESS_THRESHOLD, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::*, MockXInput};
+    use imgui::{internal::RawCast, BackendFlags, Context};
+    use windows::Win32::{
+        System::SystemServices::DBT_DEVNODES_CHANGED,
+        UI::{
+            Input::XboxController::{XINPUT_GAMEPAD_A, XINPUT_GAMEPAD_DPAD_LEFT},
+            WindowsAndMessaging::WM_DEVICECHANGE,
+        },
+    };
+
+    #[test]
+    fn analog_values_start_past_the_dead_zone() {
+        assert_eq!(analog(0.0, 7849.0, 32767.0), 0.0);
+        assert_eq!(analog(7849.0, 7849.0, 32767.0), 0.0);
+        assert_eq!(analog(32767.0, 7849.0, 32767.0), 1.0);
+        assert_eq!(analog(-32768.0, -7849.0, -32768.0), 1.0);
+        assert_eq!(analog(20000.0, -7849.0, -32768.0), 0.0);
+        assert_eq!(analog(142.5, 30.0, 255.0), 0.5);
+    }
+
+    fn gamepad_analog_value(ctx: &Context, key: Key) -> f32 {
+        unsafe { ctx.io().raw() }.KeysData[key as usize].AnalogValue
+    }
+
+    #[test]
+    fn gamepad_is_only_read_with_gamepad_navigation() {
+        let (_guard, mut ctx, mut backend) = setup();
+        let xinput = MockXInput::new();
+        xinput.set_gamepad(0, XINPUT_GAMEPAD::default());
+        backend.enable_gamepad_with(xinput.clone());
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(xinput.probe_count(), 0);
+        assert!(!ctx.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD));
+
+        ctx.io_mut()
+            .config_flags
+            .insert(imgui::ConfigFlags::NAV_ENABLE_GAMEPAD);
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(ctx.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD));
+    }
+
+    #[test]
+    fn gamepad_maps_buttons_sticks_and_triggers() {
+        let (_guard, mut ctx, mut backend) = setup();
+        ctx.io_mut()
+            .config_flags
+            .insert(imgui::ConfigFlags::NAV_ENABLE_GAMEPAD);
+        let xinput = MockXInput::new();
+        xinput.set_gamepad(
+            1,
+            XINPUT_GAMEPAD {
+                wButtons: XINPUT_GAMEPAD_A | XINPUT_GAMEPAD_DPAD_LEFT,
+                bLeftTrigger: 255,
+                bRightTrigger: 10,
+                sThumbLX: -32768,
+                sThumbLY: 5000,
+                ..Default::default()
+            },
+        );
+        backend.enable_gamepad_with(xinput);
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        let ui = ctx.new_frame();
+        assert!(ui.is_key_down(Key::GamepadFaceDown));
+        assert!(ui.is_key_down(Key::GamepadDpadLeft));
+        assert!(!ui.is_key_down(Key::GamepadFaceUp));
+        assert!(ui.is_key_down(Key::GamepadL2));
+        assert!(!ui.is_key_down(Key::GamepadR2));
+        assert!(ui.is_key_down(Key::GamepadLStickLeft));
+        // Inside the dead zone
+        assert!(!ui.is_key_down(Key::GamepadLStickUp));
+
+        assert_eq!(gamepad_analog_value(&ctx, Key::GamepadL2), 1.0);
+        assert_eq!(gamepad_analog_value(&ctx, Key::GamepadR2), 0.0);
+        assert_eq!(gamepad_analog_value(&ctx, Key::GamepadLStickLeft), 1.0);
+        assert_eq!(gamepad_analog_value(&ctx, Key::GamepadLStickUp), 0.0);
+    }
+
+    #[test]
+    fn gamepads_are_reprobed_on_device_change() {
+        let (_guard, mut ctx, mut backend) = setup();
+        ctx.io_mut()
+            .config_flags
+            .insert(imgui::ConfigFlags::NAV_ENABLE_GAMEPAD);
+        let xinput = MockXInput::new();
+        backend.enable_gamepad_with(xinput.clone());
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(xinput.probe_count(), 4);
+        assert!(!ctx.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD));
+
+        xinput.set_gamepad(0, XINPUT_GAMEPAD::default());
+        send(backend.platform(), WM_DEVICECHANGE, 0x8000, 0);
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(xinput.probe_count(), 4);
+
+        send(
+            backend.platform(),
+            WM_DEVICECHANGE,
+            DBT_DEVNODES_CHANGED as usize,
+            0,
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(xinput.probe_count(), 5);
+        assert!(ctx.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD));
+
+        xinput.unplug(0);
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(!ctx.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD));
+    }
+}
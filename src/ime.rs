@@ -0,0 +1,108 @@
+use imgui::sys::{ImGuiPlatformImeData, ImGuiViewport, ImVec2};
+use windows::Win32::{
+    Foundation::{HWND, POINT, RECT},
+    UI::Input::Ime::{
+        ImmGetContext, ImmReleaseContext, ImmSetCandidateWindow, ImmSetCompositionWindow,
+        CANDIDATEFORM, CFS_EXCLUDE, CFS_FORCE_POSITION, COMPOSITIONFORM,
+    },
+};
+
+/// Where the IME windows go, in client coordinates of the viewport's window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ImePlacement {
+    /// Top-left corner of the composition window, at imgui's text cursor.
+    pub(crate) cursor: POINT,
+    /// The line being edited, which the candidate list must not cover.
+    pub(crate) line: RECT,
+}
+
+pub(crate) fn ime_placement(viewport_pos: ImVec2, data: &ImGuiPlatformImeData) -> ImePlacement {
+    let x = (data.InputPos.x - viewport_pos.x) as i32;
+    let y = (data.InputPos.y - viewport_pos.y) as i32;
+    ImePlacement {
+        cursor: POINT { x, y },
+        line: RECT {
+            left: x,
+            top: y,
+            right: x + 1,
+            bottom: y + data.InputLineHeight as i32,
+        },
+    }
+}
+
+/// `io.SetPlatformImeDataFn` callback, moves the IME windows of the viewport's `HWND` next to the
+/// text being edited.
+pub(crate) unsafe extern "C" fn set_platform_ime_data(
+    viewport: *mut ImGuiViewport,
+    data: *mut ImGuiPlatformImeData,
+) {
+    let (viewport, data) = match (viewport.as_ref(), data.as_ref()) {
+        (Some(viewport), Some(data)) => (viewport, data),
+        _ => return,
+    };
+
+    let hwnd = HWND(viewport.PlatformHandleRaw as isize);
+    if hwnd.0 == 0 || !data.WantVisible {
+        return;
+    }
+
+    let himc = ImmGetContext(hwnd);
+    if himc.0 == 0 {
+        return;
+    }
+
+    let placement = ime_placement(viewport.Pos, data);
+    let composition = COMPOSITIONFORM {
+        dwStyle: CFS_FORCE_POSITION,
+        ptCurrentPos: placement.cursor,
+        rcArea: RECT::default(),
+    };
+    ImmSetCompositionWindow(himc, &composition);
+
+    let candidate = CANDIDATEFORM {
+        dwIndex: 0,
+        dwStyle: CFS_EXCLUDE,
+        ptCurrentPos: placement.cursor,
+        rcArea: placement.line,
+    };
+    ImmSetCandidateWindow(himc, &candidate);
+
+    ImmReleaseContext(hwnd, himc);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use imgui::sys::igGetMainViewport;
+
+    #[test]
+    fn placement_is_relative_to_the_viewport() {
+        let data = ImGuiPlatformImeData {
+            WantVisible: true,
+            InputPos: ImVec2 { x: 140.5, y: 75.0 },
+            InputLineHeight: 16.0,
+        };
+
+        let placement = ime_placement(ImVec2 { x: 100.0, y: 50.0 }, &data);
+
+        assert_eq!(placement.cursor, POINT { x: 40, y: 25 });
+        assert_eq!(
+            placement.line,
+            RECT {
+                left: 40,
+                top: 25,
+                right: 41,
+                bottom: 41,
+            }
+        );
+    }
+
+    #[test]
+    fn init_exposes_the_window_to_the_ime_callback() {
+        let (_guard, _ctx, _backend) = setup();
+
+        let viewport = unsafe { &*igGetMainViewport() };
+        assert_eq!(viewport.PlatformHandleRaw as isize, HWND_MAIN.0);
+    }
+}
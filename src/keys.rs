@@ -0,0 +1,546 @@
+use crate::{hiword, Win32Platform};
+use imgui::Key;
+use windows::Win32::{
+    Foundation::{HWND, LPARAM},
+    UI::{
+        Input::KeyboardAndMouse::*,
+        WindowsAndMessaging::{
+            KF_EXTENDED, WM_KEYDOWN, WM_KEYFIRST, WM_KEYLAST, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+        },
+    },
+};
+
+// Set 1 make codes of the shift keys, as returned by `MapVirtualKey(VK_xSHIFT, MAPVK_VK_TO_VSC)`.
+const SCANCODE_LSHIFT: u8 = 0x2a;
+const SCANCODE_RSHIFT: u8 = 0x36;
+
+#[inline]
+pub(crate) fn is_extended_key(l_param: LPARAM) -> bool {
+    hiword(l_param.0 as u32) as u32 & KF_EXTENDED != 0
+}
+
+#[inline]
+fn scancode(l_param: LPARAM) -> u8 {
+    (hiword(l_param.0 as u32) & 0xff) as u8
+}
+
+/// Resolves the generic `VK_SHIFT`, `VK_CONTROL` and `VK_MENU` codes of a keyboard message into
+/// their left or right variant, the same way `MapVirtualKey(.., MAPVK_VSC_TO_VK_EX)` would.
+///
+/// Shift is told apart by its scancode, Ctrl and Alt by the extended-key bit. Any other code is
+/// returned unchanged.
+pub fn resolve_sided_vk(vk: VIRTUAL_KEY, l_param: LPARAM) -> VIRTUAL_KEY {
+    match vk {
+        VK_SHIFT if scancode(l_param) == SCANCODE_RSHIFT => VK_RSHIFT,
+        VK_SHIFT if scancode(l_param) == SCANCODE_LSHIFT => VK_LSHIFT,
+        // Some drivers send a zero scancode, fall back to the left key
+        VK_SHIFT => VK_LSHIFT,
+        VK_CONTROL if is_extended_key(l_param) => VK_RCONTROL,
+        VK_CONTROL => VK_LCONTROL,
+        VK_MENU if is_extended_key(l_param) => VK_RMENU,
+        VK_MENU => VK_LMENU,
+        _ => vk,
+    }
+}
+
+/// Returns true when a left Ctrl message is the fake one Windows injects in front of AltGr.
+///
+/// On layouts with an AltGr key, pressing or releasing right Alt is preceded by a synthesized left
+/// Ctrl message carrying the same timestamp, which is how it is recognized here.
+pub(crate) fn is_altgr_control<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    vk: VIRTUAL_KEY,
+    l_param: LPARAM,
+) -> bool {
+    if vk != VK_CONTROL || is_extended_key(l_param) {
+        return false;
+    }
+
+    match platform.peek_message(window, WM_KEYFIRST, WM_KEYLAST) {
+        Some(next) => {
+            matches!(
+                next.message,
+                WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP
+            ) && next.wParam.0 == VK_MENU.0 as usize
+                && is_extended_key(next.lParam)
+                && next.time as i32 == platform.message_time()
+        }
+        None => false,
+    }
+}
+
+/// Whether AltGr is held, i.e. right Alt together with the left Ctrl Windows reports alongside it.
+pub(crate) fn is_altgr_down<P: Win32Platform>(platform: &P) -> bool {
+    platform.is_key_down(VK_RMENU) && platform.is_key_down(VK_LCONTROL)
+}
+
+/// Translates a virtual-key code into the matching imgui key.
+///
+/// `l_param` is the `lParam` of the keyboard message, its extended-key bit tells the numpad Enter
+/// key apart from the main one. The generic `VK_SHIFT`, `VK_CONTROL` and `VK_MENU` codes map to
+/// `None` and should go through [`resolve_sided_vk`] first. F13-F24 have no counterpart in the
+/// bundled Dear ImGui and map to `None` as well.
+pub fn vk_to_imgui_key(vk: VIRTUAL_KEY, l_param: LPARAM) -> Option<Key> {
+    Some(match vk {
+        VK_RETURN if is_extended_key(l_param) => Key::KeypadEnter,
+        VK_RETURN => Key::Enter,
+        VK_TAB => Key::Tab,
+        VK_LEFT => Key::LeftArrow,
+        VK_RIGHT => Key::RightArrow,
+        VK_UP => Key::UpArrow,
+        VK_DOWN => Key::DownArrow,
+        VK_PRIOR => Key::PageUp,
+        VK_NEXT => Key::PageDown,
+        VK_HOME => Key::Home,
+        VK_END => Key::End,
+        VK_INSERT => Key::Insert,
+        VK_DELETE => Key::Delete,
+        VK_BACK => Key::Backspace,
+        VK_SPACE => Key::Space,
+        VK_ESCAPE => Key::Escape,
+        VK_OEM_7 => Key::Apostrophe,
+        VK_OEM_COMMA => Key::Comma,
+        VK_OEM_MINUS => Key::Minus,
+        VK_OEM_PERIOD => Key::Period,
+        VK_OEM_2 => Key::Slash,
+        VK_OEM_1 => Key::Semicolon,
+        VK_OEM_PLUS => Key::Equal,
+        VK_OEM_4 => Key::LeftBracket,
+        VK_OEM_5 => Key::Backslash,
+        VK_OEM_6 => Key::RightBracket,
+        VK_OEM_3 => Key::GraveAccent,
+        VK_CAPITAL => Key::CapsLock,
+        VK_SCROLL => Key::ScrollLock,
+        VK_NUMLOCK => Key::NumLock,
+        VK_SNAPSHOT => Key::PrintScreen,
+        VK_PAUSE => Key::Pause,
+        VK_NUMPAD0 => Key::Keypad0,
+        VK_NUMPAD1 => Key::Keypad1,
+        VK_NUMPAD2 => Key::Keypad2,
+        VK_NUMPAD3 => Key::Keypad3,
+        VK_NUMPAD4 => Key::Keypad4,
+        VK_NUMPAD5 => Key::Keypad5,
+        VK_NUMPAD6 => Key::Keypad6,
+        VK_NUMPAD7 => Key::Keypad7,
+        VK_NUMPAD8 => Key::Keypad8,
+        VK_NUMPAD9 => Key::Keypad9,
+        VK_DECIMAL => Key::KeypadDecimal,
+        VK_DIVIDE => Key::KeypadDivide,
+        VK_MULTIPLY => Key::KeypadMultiply,
+        VK_SUBTRACT => Key::KeypadSubtract,
+        VK_ADD => Key::KeypadAdd,
+        VK_LSHIFT => Key::LeftShift,
+        VK_LCONTROL => Key::LeftCtrl,
+        VK_LMENU => Key::LeftAlt,
+        VK_LWIN => Key::LeftSuper,
+        VK_RSHIFT => Key::RightShift,
+        VK_RCONTROL => Key::RightCtrl,
+        VK_RMENU => Key::RightAlt,
+        VK_RWIN => Key::RightSuper,
+        VK_APPS => Key::Menu,
+        VK_0 => Key::Alpha0,
+        VK_1 => Key::Alpha1,
+        VK_2 => Key::Alpha2,
+        VK_3 => Key::Alpha3,
+        VK_4 => Key::Alpha4,
+        VK_5 => Key::Alpha5,
+        VK_6 => Key::Alpha6,
+        VK_7 => Key::Alpha7,
+        VK_8 => Key::Alpha8,
+        VK_9 => Key::Alpha9,
+        VK_A => Key::A,
+        VK_B => Key::B,
+        VK_C => Key::C,
+        VK_D => Key::D,
+        VK_E => Key::E,
+        VK_F => Key::F,
+        VK_G => Key::G,
+        VK_H => Key::H,
+        VK_I => Key::I,
+        VK_J => Key::J,
+        VK_K => Key::K,
+        VK_L => Key::L,
+        VK_M => Key::M,
+        VK_N => Key::N,
+        VK_O => Key::O,
+        VK_P => Key::P,
+        VK_Q => Key::Q,
+        VK_R => Key::R,
+        VK_S => Key::S,
+        VK_T => Key::T,
+        VK_U => Key::U,
+        VK_V => Key::V,
+        VK_W => Key::W,
+        VK_X => Key::X,
+        VK_Y => Key::Y,
+        VK_Z => Key::Z,
+        VK_F1 => Key::F1,
+        VK_F2 => Key::F2,
+        VK_F3 => Key::F3,
+        VK_F4 => Key::F4,
+        VK_F5 => Key::F5,
+        VK_F6 => Key::F6,
+        VK_F7 => Key::F7,
+        VK_F8 => Key::F8,
+        VK_F9 => Key::F9,
+        VK_F10 => Key::F10,
+        VK_F11 => Key::F11,
+        VK_F12 => Key::F12,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use imgui::Key;
+    use windows::Win32::{
+        Foundation::WPARAM,
+        UI::WindowsAndMessaging::{MSG, WM_KEYDOWN},
+    };
+
+    // lParam of a keyboard message with the extended-key flag set.
+    const EXTENDED: LPARAM = LPARAM(1 << 24);
+
+    #[test]
+    fn maps_named_keys() {
+        let table = [
+            (VK_TAB, Key::Tab),
+            (VK_LEFT, Key::LeftArrow),
+            (VK_RIGHT, Key::RightArrow),
+            (VK_UP, Key::UpArrow),
+            (VK_DOWN, Key::DownArrow),
+            (VK_PRIOR, Key::PageUp),
+            (VK_NEXT, Key::PageDown),
+            (VK_HOME, Key::Home),
+            (VK_END, Key::End),
+            (VK_INSERT, Key::Insert),
+            (VK_DELETE, Key::Delete),
+            (VK_BACK, Key::Backspace),
+            (VK_SPACE, Key::Space),
+            (VK_RETURN, Key::Enter),
+            (VK_ESCAPE, Key::Escape),
+            (VK_APPS, Key::Menu),
+            (VK_SNAPSHOT, Key::PrintScreen),
+            (VK_PAUSE, Key::Pause),
+            (VK_CAPITAL, Key::CapsLock),
+            (VK_NUMLOCK, Key::NumLock),
+            (VK_SCROLL, Key::ScrollLock),
+        ];
+        for (vk, key) in table {
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), Some(key), "{:?}", vk);
+        }
+    }
+
+    #[test]
+    fn maps_number_row_and_letters() {
+        let digits = [
+            Key::Alpha0,
+            Key::Alpha1,
+            Key::Alpha2,
+            Key::Alpha3,
+            Key::Alpha4,
+            Key::Alpha5,
+            Key::Alpha6,
+            Key::Alpha7,
+            Key::Alpha8,
+            Key::Alpha9,
+        ];
+        for (offset, key) in digits.into_iter().enumerate() {
+            let vk = VIRTUAL_KEY(VK_0.0 + offset as u16);
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), Some(key));
+        }
+
+        let letters = [
+            Key::A,
+            Key::B,
+            Key::C,
+            Key::D,
+            Key::E,
+            Key::F,
+            Key::G,
+            Key::H,
+            Key::I,
+            Key::J,
+            Key::K,
+            Key::L,
+            Key::M,
+            Key::N,
+            Key::O,
+            Key::P,
+            Key::Q,
+            Key::R,
+            Key::S,
+            Key::T,
+            Key::U,
+            Key::V,
+            Key::W,
+            Key::X,
+            Key::Y,
+            Key::Z,
+        ];
+        for (offset, key) in letters.into_iter().enumerate() {
+            let vk = VIRTUAL_KEY(VK_A.0 + offset as u16);
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), Some(key));
+        }
+    }
+
+    #[test]
+    fn maps_function_keys() {
+        let keys = [
+            Key::F1,
+            Key::F2,
+            Key::F3,
+            Key::F4,
+            Key::F5,
+            Key::F6,
+            Key::F7,
+            Key::F8,
+            Key::F9,
+            Key::F10,
+            Key::F11,
+            Key::F12,
+        ];
+        for (offset, key) in keys.into_iter().enumerate() {
+            let vk = VIRTUAL_KEY(VK_F1.0 + offset as u16);
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), Some(key));
+        }
+        for vk in VK_F13.0..=VK_F24.0 {
+            assert_eq!(vk_to_imgui_key(VIRTUAL_KEY(vk), LPARAM(0)), None);
+        }
+    }
+
+    #[test]
+    fn maps_oem_punctuation() {
+        let table = [
+            (VK_OEM_7, Key::Apostrophe),
+            (VK_OEM_COMMA, Key::Comma),
+            (VK_OEM_MINUS, Key::Minus),
+            (VK_OEM_PERIOD, Key::Period),
+            (VK_OEM_2, Key::Slash),
+            (VK_OEM_1, Key::Semicolon),
+            (VK_OEM_PLUS, Key::Equal),
+            (VK_OEM_4, Key::LeftBracket),
+            (VK_OEM_5, Key::Backslash),
+            (VK_OEM_6, Key::RightBracket),
+            (VK_OEM_3, Key::GraveAccent),
+        ];
+        for (vk, key) in table {
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), Some(key), "{:?}", vk);
+        }
+    }
+
+    #[test]
+    fn maps_numpad() {
+        let table = [
+            (VK_NUMPAD0, Key::Keypad0),
+            (VK_NUMPAD1, Key::Keypad1),
+            (VK_NUMPAD2, Key::Keypad2),
+            (VK_NUMPAD3, Key::Keypad3),
+            (VK_NUMPAD4, Key::Keypad4),
+            (VK_NUMPAD5, Key::Keypad5),
+            (VK_NUMPAD6, Key::Keypad6),
+            (VK_NUMPAD7, Key::Keypad7),
+            (VK_NUMPAD8, Key::Keypad8),
+            (VK_NUMPAD9, Key::Keypad9),
+            (VK_DECIMAL, Key::KeypadDecimal),
+            (VK_DIVIDE, Key::KeypadDivide),
+            (VK_MULTIPLY, Key::KeypadMultiply),
+            (VK_SUBTRACT, Key::KeypadSubtract),
+            (VK_ADD, Key::KeypadAdd),
+        ];
+        for (vk, key) in table {
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), Some(key), "{:?}", vk);
+        }
+        assert_eq!(vk_to_imgui_key(VK_RETURN, EXTENDED), Some(Key::KeypadEnter));
+    }
+
+    #[test]
+    fn maps_sided_modifiers() {
+        let table = [
+            (VK_LSHIFT, Some(Key::LeftShift)),
+            (VK_RSHIFT, Some(Key::RightShift)),
+            (VK_LCONTROL, Some(Key::LeftCtrl)),
+            (VK_RCONTROL, Some(Key::RightCtrl)),
+            (VK_LMENU, Some(Key::LeftAlt)),
+            (VK_RMENU, Some(Key::RightAlt)),
+            (VK_LWIN, Some(Key::LeftSuper)),
+            (VK_RWIN, Some(Key::RightSuper)),
+            (VK_SHIFT, None),
+            (VK_CONTROL, None),
+            (VK_MENU, None),
+        ];
+        for (vk, key) in table {
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), key, "{:?}", vk);
+        }
+    }
+
+    #[test]
+    fn resolves_sided_modifiers() {
+        let lparam = |scancode: isize, extended: bool| {
+            LPARAM(scancode << 16 | if extended { EXTENDED.0 } else { 0 })
+        };
+        let table = [
+            (VK_SHIFT, lparam(0x2a, false), VK_LSHIFT),
+            (VK_SHIFT, lparam(0x36, false), VK_RSHIFT),
+            (VK_SHIFT, lparam(0, false), VK_LSHIFT),
+            (VK_CONTROL, lparam(0x1d, false), VK_LCONTROL),
+            (VK_CONTROL, lparam(0x1d, true), VK_RCONTROL),
+            (VK_MENU, lparam(0x38, false), VK_LMENU),
+            (VK_MENU, lparam(0x38, true), VK_RMENU),
+            (VK_RETURN, lparam(0x1c, true), VK_RETURN),
+            (VK_A, lparam(0x1e, false), VK_A),
+        ];
+        for (vk, l_param, sided) in table {
+            assert_eq!(
+                resolve_sided_vk(vk, l_param),
+                sided,
+                "{:?} {:?}",
+                vk,
+                l_param
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_unmapped_codes() {
+        for vk in [VK_LBUTTON, VK_CLEAR, VK_SLEEP, VK_VOLUME_UP, VK_PACKET] {
+            assert_eq!(vk_to_imgui_key(vk, LPARAM(0)), None);
+        }
+    }
+
+    #[test]
+    fn prepare_frame_reads_modifiers() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.platform().set_key_down(VK_CONTROL, true);
+        backend.platform().set_key_down(VK_MENU, true);
+
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let ui = ctx.new_frame();
+        assert!(ui.io().key_ctrl);
+        assert!(!ui.io().key_shift);
+        assert!(ui.io().key_alt);
+    }
+
+    #[test]
+    fn key_messages_queue_key_events() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(platform, WM_KEYDOWN, VK_TAB.0 as usize, 0);
+        send(platform, WM_KEYDOWN, VK_RETURN.0 as usize, 0);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_key_down(Key::Tab));
+        assert!(ui.is_key_down(Key::Enter));
+        assert!(!ui.is_key_down(Key::Escape));
+    }
+
+    #[test]
+    fn key_messages_submit_modifiers() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        platform.set_key_down(VK_SHIFT, true);
+        send(platform, WM_KEYDOWN, VK_SHIFT.0 as usize, 0);
+
+        let ui = ctx.new_frame();
+        assert!(ui.io().key_shift);
+    }
+
+    #[test]
+    fn generic_modifiers_resolve_to_sided_keys() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        platform.set_key_down(VK_RSHIFT, true);
+        send(platform, WM_KEYDOWN, VK_SHIFT.0 as usize, 0x36 << 16);
+        platform.set_key_down(VK_RCONTROL, true);
+        send(platform, WM_KEYDOWN, VK_CONTROL.0 as usize, 0x11d << 16);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_key_down(Key::RightShift));
+        assert!(!ui.is_key_down(Key::LeftShift));
+        assert!(ui.is_key_down(Key::RightCtrl));
+        assert!(!ui.is_key_down(Key::LeftCtrl));
+        assert!(ui.io().key_shift);
+        assert!(ui.io().key_ctrl);
+    }
+
+    #[test]
+    fn altgr_is_not_reported_as_ctrl() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+        let right_alt = MSG {
+            hwnd: HWND_MAIN,
+            message: WM_KEYDOWN,
+            wParam: WPARAM(VK_MENU.0 as usize),
+            lParam: LPARAM(0x138 << 16),
+            time: 42,
+            ..Default::default()
+        };
+
+        platform.set_message_time(42);
+        platform.post_message(right_alt);
+        platform.set_key_down(VK_LCONTROL, true);
+        send(platform, WM_KEYDOWN, VK_CONTROL.0 as usize, 0x1d << 16);
+        platform.clear_messages();
+        platform.set_key_down(VK_RMENU, true);
+        send(platform, WM_KEYDOWN, VK_MENU.0 as usize, 0x138 << 16);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_key_down(Key::RightAlt));
+        assert!(!ui.is_key_down(Key::LeftCtrl));
+        assert!(!ui.io().key_ctrl);
+        assert!(ui.io().key_alt);
+    }
+
+    #[test]
+    fn left_ctrl_with_a_later_timestamp_is_kept() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        platform.set_message_time(41);
+        platform.post_message(MSG {
+            hwnd: HWND_MAIN,
+            message: WM_KEYDOWN,
+            wParam: WPARAM(VK_MENU.0 as usize),
+            lParam: LPARAM(0x138 << 16),
+            time: 42,
+            ..Default::default()
+        });
+        platform.set_key_down(VK_LCONTROL, true);
+        send(platform, WM_KEYDOWN, VK_CONTROL.0 as usize, 0x1d << 16);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_key_down(Key::LeftCtrl));
+        assert!(ui.io().key_ctrl);
+    }
+
+    #[test]
+    fn prepare_frame_reads_super() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.platform().set_key_down(VK_RWIN, true);
+
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let ui = ctx.new_frame();
+        assert!(ui.io().key_super);
+    }
+
+    #[test]
+    fn windows_key_messages_submit_super() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        platform.set_key_down(VK_LWIN, true);
+        send(platform, WM_KEYDOWN, VK_LWIN.0 as usize, 0x15b << 16);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_key_down(Key::LeftSuper));
+        assert!(ui.io().key_super);
+    }
+}
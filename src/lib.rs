@@ -1,32 +1,114 @@
+use clipboard::DefaultClipboard;
 use imgui::{
+    internal::RawCast,
     sys::{
-        igGetIO, igGetMouseCursor, igIsAnyMouseDown, ImGuiConfigFlags_NoMouseCursorChange,
-        ImGuiIO_AddInputCharacterUTF16, ImGuiMouseCursor, ImGuiMouseCursor_Arrow,
-        ImGuiMouseCursor_Hand, ImGuiMouseCursor_None, ImGuiMouseCursor_NotAllowed,
-        ImGuiMouseCursor_ResizeAll, ImGuiMouseCursor_ResizeEW, ImGuiMouseCursor_ResizeNESW,
-        ImGuiMouseCursor_ResizeNS, ImGuiMouseCursor_ResizeNWSE, ImGuiMouseCursor_TextInput,
+        igGetIO, igGetMainViewport, igGetMouseCursor, ImGuiIO_AddFocusEvent,
+        ImGuiIO_AddInputCharacterUTF16, ImGuiKey, ImGuiKey_MouseLeft, ImGuiMouseCursor,
+        ImGuiMouseCursor_COUNT, ImGuiMouseCursor_None,
     },
-    BackendFlags, Context, Key,
+    BackendFlags, ConfigFlags, Context, Io, Key, MouseButton,
 };
-use std::time::Instant;
+// The docking bindings also export imgui_internal.h, where IsKeyDown is overloaded
+#[cfg(not(feature = "docking"))]
+use imgui::sys::igIsKeyDown;
+#[cfg(feature = "docking")]
+use imgui::sys::igIsKeyDown_Nil as igIsKeyDown;
+use state::{MouseArea, SharedWindowState, WindowState};
+use std::{cell::RefMut, rc::Rc, time::Instant};
 use thiserror::Error;
 use windows::Win32::{
-    Foundation::{GetLastError, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
-    Graphics::Gdi::{ClientToScreen, ScreenToClient},
-    UI::{Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
+    Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM},
+    System::SystemServices::{
+        DBT_DEVNODES_CHANGED, MK_LBUTTON, MK_MBUTTON, MK_RBUTTON, MK_XBUTTON1, MK_XBUTTON2,
+    },
+    UI::{Controls::WM_MOUSELEAVE, Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
 };
 
+mod clipboard;
+mod cursor;
+mod gamepad;
+mod ime;
+mod keys;
+mod monitor;
+mod mouse;
+mod platform;
+mod pointer;
+mod state;
+mod subclass;
+#[cfg(test)]
+mod test_util;
+mod text;
+#[cfg(feature = "docking")]
+mod viewports;
+
+pub use clipboard::Win32Clipboard;
+pub use cursor::{create_cursor_rgba, load_cursor_file, CursorHook, CursorMap, RequestedCursor};
+pub use gamepad::{MockXInput, NativeXInput, XInput};
+pub use keys::{resolve_sided_vk, vk_to_imgui_key};
+pub use monitor::MonitorInfo;
+pub use mouse::{mouse_source_from_extra_info, CapturePolicy, MouseSource};
+pub use platform::{MockPlatform, MockWindow, NativePlatform, Win32Platform};
+pub use pointer::{PointerContact, PointerFrame};
+pub use subclass::WindowSubclass;
+
 pub type WindowProc = unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> LRESULT;
 
+/// Called by `prepare_frame` with the new DPI scale after the window moved to a monitor with a
+/// different DPI, before the frame starts.
+pub type DpiChangedCallback = Box<dyn FnMut(&mut Context, f32)>;
+
+/// What the window procedure should do with a message after imgui saw it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcResponse {
-    NoAction,
-    ActionTaken,
+    /// Handle the message as usual.
+    PassThrough,
+    /// imgui wants the input the message carries, e.g. a click over one of its windows, going by
+    /// `want_capture_mouse`, `want_capture_keyboard` and `want_text_input` of the last frame. The
+    /// application shouldn't act on it, the message still goes to `DefWindowProc`.
+    ///
+    /// A window procedure that swallows the message instead returns the value carried here, `TRUE`
+    /// for `WM_XBUTTON*`, or 0 when there is none.
+    Consumed(Option<LRESULT>),
+    /// The message was handled, the window procedure should return this without passing it on.
+    ///
+    /// It is the value Win32 expects for the message, `TRUE` for `WM_SETCURSOR` and the
+    /// `WM_UNICHAR` probe, `FALSE` for a `WM_UNICHAR` character and 0 for `WM_POINTER*`.
+    Handled(LRESULT),
 }
 
-pub struct Win32Impl {
+impl ProcResponse {
+    /// The value to return from the window procedure as is, e.g. `TRUE` for `WM_SETCURSOR` once
+    /// imgui set the cursor, `None` when the message is left to the application.
+    pub fn lresult(self) -> Option<LRESULT> {
+        match self {
+            ProcResponse::Handled(result) => Some(result),
+            ProcResponse::PassThrough | ProcResponse::Consumed(_) => None,
+        }
+    }
+
+    /// The value to return from the window procedure, chaining to `default_proc` for messages imgui
+    /// didn't handle, usually `DefWindowProcW` or `CallWindowProcW` of the original procedure.
+    pub fn into_lresult(self, default_proc: impl FnOnce() -> LRESULT) -> LRESULT {
+        self.lresult().unwrap_or_else(default_proc)
+    }
+}
+
+pub struct Win32Impl<P: Win32Platform = NativePlatform> {
     hwnd: HWND,
     time: Instant,
     last_cursor: ImGuiMouseCursor,
+    platform: Rc<P>,
+    state: SharedWindowState,
+    mouse_polling: bool,
+    pointer_frame: PointerFrame,
+    gamepad: Option<gamepad::Gamepad>,
+    dpi_changed: Option<DpiChangedCallback>,
+    monitors: Vec<MonitorInfo>,
+    clipboard_enabled: bool,
+    default_clipboard: Option<DefaultClipboard>,
+    /// The context the viewports were set up on.
+    #[cfg(feature = "docking")]
+    context: *mut imgui::sys::ImGuiContext,
 }
 
 #[inline]
@@ -39,73 +121,367 @@ fn hiword(l: u32) -> u16 {
     ((l >> 16) & 0xffff) as u16
 }
 
+// Coordinates are signed, monitors left of or above the primary one have negative positions
+#[inline]
+fn get_x_lparam(l: u32) -> i32 {
+    loword(l) as i16 as i32
+}
+
+#[inline]
+fn get_y_lparam(l: u32) -> i32 {
+    hiword(l) as i16 as i32
+}
+
+// With multi-viewports on, imgui's mouse position is in screen coordinates
+#[cfg(feature = "docking")]
+fn viewports_enabled(io: &Io) -> bool {
+    io.config_flags
+        .contains(imgui::ConfigFlags::VIEWPORTS_ENABLE)
+}
+
+#[cfg(not(feature = "docking"))]
+fn viewports_enabled(_io: &Io) -> bool {
+    false
+}
+
 fn get_xbutton_wparam(w_param: u32) -> MOUSEHOOKSTRUCTEX_MOUSE_DATA {
     MOUSEHOOKSTRUCTEX_MOUSE_DATA(hiword(w_param) as u32)
 }
 
-fn get_wheel_delta_wparam(w_param: u32) -> u32 {
-    hiword(w_param) as u32
+fn get_wheel_delta_wparam(w_param: u32) -> i16 {
+    hiword(w_param) as i16
+}
+
+// Fractions of a notch are kept so precision touchpads scroll smoothly
+fn wheel_notches(w_param: u32) -> f32 {
+    get_wheel_delta_wparam(w_param) as f32 / WHEEL_DELTA as f32
+}
+
+// The response to input imgui had no answer of its own for
+fn capture_response(io: &Io, msg: u32) -> ProcResponse {
+    let consumed = match msg {
+        WM_MOUSEFIRST..=WM_MOUSELAST => io.want_capture_mouse,
+        WM_KEYDOWN | WM_KEYUP | WM_SYSKEYDOWN | WM_SYSKEYUP => io.want_capture_keyboard,
+        WM_CHAR | WM_DEADCHAR | WM_SYSCHAR | WM_SYSDEADCHAR | WM_UNICHAR => {
+            io.want_capture_keyboard || io.want_text_input
+        }
+        _ => false,
+    };
+    // Unlike the other button messages, WM_XBUTTON* must be answered with TRUE
+    let result = match msg {
+        WM_XBUTTONDOWN | WM_XBUTTONDBLCLK | WM_XBUTTONUP => Some(LRESULT(1)),
+        _ => None,
+    };
+    match consumed {
+        true => ProcResponse::Consumed(result),
+        false => ProcResponse::PassThrough,
+    }
+}
+
+fn any_button_down_wparam(w_param: u32) -> bool {
+    let buttons = MK_LBUTTON | MK_RBUTTON | MK_MBUTTON | MK_XBUTTON1 | MK_XBUTTON2;
+    w_param & buttons.0 != 0
+}
+
+fn update_key_modifiers<P: Win32Platform>(platform: &P, io: &mut Io) {
+    // AltGr is reported by Windows as left Ctrl + right Alt, only keep the Alt part
+    let ctrl = platform.is_key_down(VK_CONTROL)
+        && (platform.is_key_down(VK_RCONTROL) || !keys::is_altgr_down(platform));
+    io.add_key_event(Key::ModCtrl, ctrl);
+    io.add_key_event(Key::ModShift, platform.is_key_down(VK_SHIFT));
+    io.add_key_event(Key::ModAlt, platform.is_key_down(VK_MENU));
+    io.add_key_event(
+        Key::ModSuper,
+        platform.is_key_down(VK_LWIN) || platform.is_key_down(VK_RWIN),
+    );
 }
 
 impl Win32Impl {
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn init(imgui: &mut Context, hwnd: HWND) -> Result<Win32Impl, Win32ImplError> {
+    /// Sets up `imgui` for input from `hwnd`.
+    ///
+    /// Fails with `Win32ImplError::InvalidWindow` unless `hwnd` is a window. The backend keeps
+    /// per-window state on the calling thread, it must be used from the thread owning `hwnd`, and
+    /// for as long as the window lives.
+    pub fn init(imgui: &mut Context, hwnd: HWND) -> Result<Win32Impl, Win32ImplError> {
+        let backend = Self::init_with_platform(imgui, hwnd, NativePlatform)?;
+        imgui.io_mut().set_platform_ime_data_fn = Some(ime::set_platform_ime_data);
+        Ok(backend)
+    }
+}
+
+impl<P: Win32Platform> Win32Impl<P> {
+    /// Like `init`, going through `platform`.
+    pub fn init_with_platform(
+        imgui: &mut Context,
+        hwnd: HWND,
+        platform: P,
+    ) -> Result<Win32Impl<P>, Win32ImplError> {
+        if !platform.is_window(hwnd) {
+            return Err(Win32ImplError::InvalidWindow(hwnd));
+        }
+
         let time = Instant::now();
         let io = imgui.io_mut();
 
         io.backend_flags.insert(BackendFlags::HAS_MOUSE_CURSORS);
         io.backend_flags.insert(BackendFlags::HAS_SET_MOUSE_POS);
 
-        io.key_map[Key::Tab as usize] = VK_TAB.0 as u32;
-        io.key_map[Key::LeftArrow as usize] = VK_LEFT.0 as u32;
-        io.key_map[Key::RightArrow as usize] = VK_RIGHT.0 as u32;
-        io.key_map[Key::UpArrow as usize] = VK_UP.0 as u32;
-        io.key_map[Key::DownArrow as usize] = VK_DOWN.0 as u32;
-        io.key_map[Key::PageUp as usize] = VK_PRIOR.0 as u32;
-        io.key_map[Key::PageDown as usize] = VK_NEXT.0 as u32;
-        io.key_map[Key::Home as usize] = VK_HOME.0 as u32;
-        io.key_map[Key::End as usize] = VK_END.0 as u32;
-        io.key_map[Key::Insert as usize] = VK_INSERT.0 as u32;
-        io.key_map[Key::Delete as usize] = VK_DELETE.0 as u32;
-        io.key_map[Key::Backspace as usize] = VK_BACK.0 as u32;
-        io.key_map[Key::Space as usize] = VK_SPACE.0 as u32;
-        io.key_map[Key::KeyPadEnter as usize] = VK_RETURN.0 as u32;
-        io.key_map[Key::Escape as usize] = VK_ESCAPE.0 as u32;
-        io.key_map[Key::KeyPadEnter as usize] = VK_RETURN.0 as u32;
-        io.key_map[Key::A as usize] = 'A' as u32;
-        io.key_map[Key::C as usize] = 'C' as u32;
-        io.key_map[Key::V as usize] = 'V' as u32;
-        io.key_map[Key::X as usize] = 'X' as u32;
-        io.key_map[Key::Y as usize] = 'Y' as u32;
-        io.key_map[Key::Z as usize] = 'Z' as u32;
+        // `init` installs an IME callback that finds the window to position the IME on through this
+        // handle
+        // SAFETY: `imgui` is the current context, its main viewport lives as long as it does
+        unsafe { (*igGetMainViewport()).PlatformHandleRaw = hwnd.0 as *mut std::ffi::c_void };
 
         imgui.set_platform_name(format!("imgui-win32 {}", env!("CARGO_PKG_VERSION")));
 
         let last_cursor = ImGuiMouseCursor_None;
 
+        let state = state::register(hwnd);
+        state.borrow_mut().dpi = platform.dpi_for_window(hwnd);
+
+        let monitors = platform.monitors();
+        monitor::update_platform_io(imgui, &monitors);
+
+        let platform = Rc::new(platform);
+        // A `ClipboardBackend` the application installed already is left in place
+        let default_clipboard = DefaultClipboard::current(imgui);
+        let clipboard_enabled = default_clipboard.is_some();
+        if clipboard_enabled {
+            imgui.set_clipboard_backend(Win32Clipboard::with_platform(platform.clone(), hwnd));
+        }
+        #[cfg(feature = "docking")]
+        let context = unsafe { viewports::init(imgui, hwnd, platform.clone()) };
+
         Ok(Win32Impl {
             hwnd,
             time,
             last_cursor,
+            platform,
+            state,
+            mouse_polling: false,
+            pointer_frame: PointerFrame::default(),
+            gamepad: None,
+            dpi_changed: None,
+            monitors,
+            clipboard_enabled,
+            default_clipboard,
+            #[cfg(feature = "docking")]
+            context,
         })
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn prepare_frame(&mut self, context: &mut Context) -> Result<(), Win32ImplError> {
+    /// Whether `prepare_frame` samples the cursor position with `GetCursorPos`.
+    ///
+    /// The position normally comes from `WM_MOUSEMOVE` / `WM_NCMOUSEMOVE`, which keeps every
+    /// intermediate position of a fast drag. Polling is a fallback for hosts that don't forward
+    /// mouse messages to `imgui_win32_window_proc`.
+    pub fn set_mouse_polling(&mut self, enabled: bool) {
+        self.mouse_polling = enabled;
+    }
+
+    pub fn mouse_polling(&self) -> bool {
+        self.mouse_polling
+    }
+
+    /// When a button press takes the mouse capture, `CapturePolicy::Always` by default. Change it
+    /// for applications that capture the mouse themselves, e.g. for drag and drop or a custom title
+    /// bar.
+    pub fn set_capture_policy(&mut self, policy: CapturePolicy) {
+        self.state.borrow_mut().capture_policy = policy;
+    }
+
+    pub fn capture_policy(&self) -> CapturePolicy {
+        self.state.borrow().capture_policy
+    }
+
+    /// The device behind the last mouse message, touch and pen input arrive as synthesized mouse
+    /// messages.
+    pub fn mouse_source(&self) -> MouseSource {
+        self.state.borrow().mouse_source
+    }
+
+    /// Handle `WM_POINTER*` messages of touch and pens natively, with multi-touch and pressure
+    /// reported through `pointers`. Disabled by default, Windows then turns them into mouse
+    /// messages.
+    pub fn set_pointer_input(&mut self, enabled: bool) {
+        self.state.borrow_mut().pointer_input = enabled;
+    }
+
+    pub fn pointer_input(&self) -> bool {
+        self.state.borrow().pointer_input
+    }
+
+    /// Read the first connected XInput controller in `prepare_frame` for gamepad navigation, while
+    /// `ConfigFlags::NAV_ENABLE_GAMEPAD` is set.
+    pub fn enable_gamepad(&mut self) {
+        self.enable_gamepad_with(NativeXInput);
+    }
+
+    /// Like `enable_gamepad`, reading controllers through `xinput`.
+    pub fn enable_gamepad_with<X: XInput + 'static>(&mut self, xinput: X) {
+        self.gamepad = Some(gamepad::Gamepad::new(Box::new(xinput)));
+    }
+
+    pub fn disable_gamepad(&mut self) {
+        self.gamepad = None;
+    }
+
+    /// Scale factor of the monitor the window is on, `1.0` at 96 DPI.
+    ///
+    /// `display_size` is in physical pixels for DPI aware processes, so imgui isn't scaled by
+    /// `display_framebuffer_scale`. Fonts and `Style::scale_all_sizes` are where this applies.
+    pub fn dpi_scale(&self) -> f32 {
+        self.state.borrow().dpi as f32 / USER_DEFAULT_SCREEN_DPI as f32
+    }
+
+    /// Installs a callback to rebuild fonts and style when `WM_DPICHANGED` changes `dpi_scale`.
+    pub fn set_dpi_changed_callback<F: FnMut(&mut Context, f32) + 'static>(&mut self, callback: F) {
+        self.dpi_changed = Some(Box::new(callback));
+    }
+
+    /// Whether a `Win32Clipboard` serves imgui's copy and paste. `init` installs one unless
+    /// `imgui` has a `ClipboardBackend` already, turning it off puts imgui's own clipboard back.
+    ///
+    /// A `ClipboardBackend` the application installed stays in place, it would be dropped by
+    /// installing another one. Enabling is ignored while there is one, `clipboard_enabled` tells.
+    pub fn set_clipboard_enabled(&mut self, imgui: &mut Context, enabled: bool) {
+        if enabled == self.clipboard_enabled {
+            return;
+        }
+
+        if enabled {
+            match DefaultClipboard::current(imgui) {
+                Some(default_clipboard) => self.default_clipboard = Some(default_clipboard),
+                None => return,
+            }
+            imgui.set_clipboard_backend(Win32Clipboard::with_platform(
+                self.platform.clone(),
+                self.hwnd,
+            ));
+        } else if let Some(default_clipboard) = self.default_clipboard {
+            default_clipboard.restore(imgui);
+        }
+        self.clipboard_enabled = enabled;
+    }
+
+    pub fn clipboard_enabled(&self) -> bool {
+        self.clipboard_enabled
+    }
+
+    /// Replaces the cursors shown for imgui's mouse cursors, from the next `prepare_frame` on.
+    pub fn set_cursor_map(&mut self, cursors: CursorMap) {
+        self.state.borrow_mut().cursors = cursors;
+        self.last_cursor = ImGuiMouseCursor_COUNT;
+    }
+
+    /// Edits the cursor map in place, changes show from the next `prepare_frame` on.
+    pub fn cursor_map_mut(&mut self) -> RefMut<'_, CursorMap> {
+        self.last_cursor = ImGuiMouseCursor_COUNT;
+        RefMut::map(self.state.borrow_mut(), |state| &mut state.cursors)
+    }
+
+    /// Monitors of the desktop, the primary one first. Enumerated by `init` and again by
+    /// `prepare_frame` after `WM_DISPLAYCHANGE`.
+    pub fn monitors(&self) -> &[MonitorInfo] {
+        &self.monitors
+    }
+
+    /// Touch contacts and pens seen since the previous `prepare_frame`.
+    pub fn pointers(&self) -> &PointerFrame {
+        &self.pointer_frame
+    }
+
+    pub fn platform(&self) -> &P {
+        &self.platform
+    }
+
+    /// Hooks `hwnd`'s window procedure to feed its messages to imgui, for hosts that can't forward
+    /// them, e.g. overlays in another application's window. Messages go on to the window
+    /// procedure unless `WindowSubclass::set_swallow_input` keeps them from it.
+    ///
+    /// # Safety
+    ///
+    /// Messages reach imgui through `imgui_win32_window_proc`, whose requirements hold for as long
+    /// as the `WindowSubclass` lives. It must be dropped on the thread owning `hwnd`.
+    pub unsafe fn subclass(&self, hwnd: HWND) -> Result<WindowSubclass<P>, Win32ImplError> {
+        if !self.platform.is_window(hwnd) {
+            return Err(Win32ImplError::InvalidWindow(hwnd));
+        }
+        WindowSubclass::install(self.platform.clone(), hwnd)
+    }
+
+    /// Feeds a message of `hwnd` to `ctx`, for applications running several contexts.
+    ///
+    /// `imgui_win32_window_proc` finds the context through `igGetIO` and the window's state through
+    /// a per-thread registry, which picks the wrong ones once two contexts take turns or two
+    /// `Win32Impl`s share a window. Here messages of this instance's window use its own state, other
+    /// windows (e.g. viewports) are still looked up. `ctx` should be the context this instance was
+    /// initialized with.
+    ///
+    /// Releasing the mouse capture taken for a press sends `WM_CAPTURECHANGED` back to the window
+    /// procedure before this returns, while the caller still holds `ctx`. The mouse buttons are
+    /// released by then, so a window procedure that can't reach the context at that point, e.g.
+    /// because it is kept in a borrowed `RefCell`, can leave that message to `DefWindowProc`.
+    pub fn handle_message(
+        &mut self,
+        ctx: &mut Context,
+        hwnd: HWND,
+        msg: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> ProcResponse {
+        let state = match hwnd == self.hwnd {
+            true => Some(self.state.clone()),
+            false => state::lookup(hwnd),
+        };
+        // An imgui-rs `Context` is only reachable while it is the current one
+        let dispatch = unsafe {
+            window_proc(
+                &*self.platform,
+                ctx.io_mut(),
+                state.as_ref(),
+                hwnd,
+                msg,
+                w_param,
+                l_param,
+            )
+        };
+        dispatch.finish(&*self.platform)
+    }
+
+    /// Feeds the window's size, the time step, polled input and the cursor shape to `context`,
+    /// before `Context::new_frame`.
+    ///
+    /// `context` must be the one passed to `init`. Fails with `Win32ImplError::InvalidWindow` once
+    /// the window is destroyed.
+    pub fn prepare_frame(&mut self, context: &mut Context) -> Result<(), Win32ImplError> {
+        if !self.platform.is_window(self.hwnd) {
+            return Err(Win32ImplError::InvalidWindow(self.hwnd));
+        }
+
+        let dpi_changed = std::mem::take(&mut self.state.borrow_mut().dpi_changed);
+        if dpi_changed {
+            let scale = self.dpi_scale();
+            if let Some(callback) = &mut self.dpi_changed {
+                callback(context, scale);
+            }
+        }
+
+        let displays_changed = std::mem::take(&mut self.state.borrow_mut().displays_changed);
+        if displays_changed {
+            self.monitors = self.platform.monitors();
+            monitor::update_platform_io(context, &self.monitors);
+        }
+
         let io = context.io_mut();
 
         // Set up display size every frame to handle resizing
-        let mut rect: RECT = std::mem::zeroed();
-        if GetClientRect(self.hwnd, &mut rect).as_bool() == false {
-            return Err(Win32ImplError::ExternalError(format!(
-                "GetClientRect failed with last error `{:#?}`",
-                GetLastError()
-            )));
-        };
+        let rect = self.platform.client_rect(self.hwnd)?;
 
         let width = (rect.right - rect.left) as f32;
         let height = (rect.bottom - rect.top) as f32;
         io.display_size = [width, height];
+        // The client area is in physical pixels already
+        io.display_framebuffer_scale = [1.0, 1.0];
 
         // Perform time step
         let current_time = Instant::now();
@@ -113,193 +489,559 @@ impl Win32Impl {
         io.delta_time = current_time.duration_since(last_time).as_secs_f32();
         self.time = current_time;
 
+        self.pointer_frame = self.state.borrow_mut().pointers.end_frame();
+
+        let devices_changed = std::mem::take(&mut self.state.borrow_mut().devices_changed);
+        if let Some(gamepad) = &mut self.gamepad {
+            if devices_changed {
+                gamepad.request_probe();
+            }
+            gamepad.update(io);
+        }
+
         // Read key states
-        io.key_ctrl = (GetKeyState(VK_CONTROL.0 as i32) as u16 & 0x8000) != 0;
-        io.key_shift = (GetKeyState(VK_SHIFT.0 as i32) as u16 & 0x8000) != 0;
-        io.key_alt = (GetKeyState(VK_MENU.0 as i32) as u16 & 0x8000) != 0;
-        io.key_super = false;
+        update_key_modifiers(&*self.platform, io);
+
+        // Windows doesn't send WM_KEYUP for the first shift released while both are held, nor
+        // for the Windows key when the shell swallows a Win+key shortcut
+        for (key, vk) in [
+            (Key::LeftShift, VK_LSHIFT),
+            (Key::RightShift, VK_RSHIFT),
+            (Key::LeftSuper, VK_LWIN),
+            (Key::RightSuper, VK_RWIN),
+        ] {
+            // SAFETY: `context` is the current context, which imgui's free functions work on
+            if unsafe { igIsKeyDown(key as ImGuiKey) } && !self.platform.is_key_down(vk) {
+                io.add_key_event(key, false);
+            }
+        }
 
         // Mouse cursor pos and icon updates
         let current_cursor = match io.mouse_draw_cursor {
             true => ImGuiMouseCursor_None,
-            false => igGetMouseCursor(),
+            false => unsafe { igGetMouseCursor() },
         };
 
         self.update_cursor_pos(context);
         if self.last_cursor != current_cursor {
             self.last_cursor = current_cursor;
-            update_cursor();
+            unsafe { update_cursor(&*self.platform, context.io(), Some(&self.state)) };
         }
 
         Ok(())
     }
 
-    unsafe fn update_cursor_pos(&self, context: &mut Context) {
+    fn update_cursor_pos(&self, context: &mut Context) {
         let io = context.io_mut();
 
+        let viewports = viewports_enabled(io);
+
         if io.want_set_mouse_pos {
-            let x = io.mouse_pos[0] as i32;
-            let y = io.mouse_pos[1] as i32;
-            let mut pos = POINT { x, y };
+            let pos = POINT {
+                x: io.mouse_pos[0] as i32,
+                y: io.mouse_pos[1] as i32,
+            };
+            let pos = match viewports {
+                true => Some(pos),
+                false => self.platform.client_to_screen(self.hwnd, pos),
+            };
+            if let Some(pos) = pos {
+                self.platform.set_cursor_pos(pos);
+            }
+        }
 
-            if ClientToScreen(self.hwnd, &mut pos).as_bool() == true {
-                SetCursorPos(pos.x, pos.y);
+        // Let imgui know which viewport is under the cursor, it can't tell on its own when
+        // viewports overlap
+        #[cfg(feature = "docking")]
+        if viewports {
+            // SAFETY: `context` is the current context, which owns `io` and the viewports
+            unsafe {
+                let hovered = match self.platform.cursor_pos() {
+                    Some(pos) => viewports::viewport_id(self.platform.window_from_point(pos)),
+                    None => 0,
+                };
+                imgui::sys::ImGuiIO_AddMouseViewportEvent(io.raw_mut(), hovered);
             }
         }
 
-        io.mouse_pos = [-f32::MAX, -f32::MAX];
-        let mut pos: POINT = std::mem::zeroed();
-        let foreground_hwnd = GetForegroundWindow();
-        if (self.hwnd == foreground_hwnd || IsChild(foreground_hwnd, self.hwnd).as_bool() == true)
-            && GetCursorPos(&mut pos).as_bool() == true
-            && ScreenToClient(self.hwnd, &mut pos).as_bool() == true
+        if !self.mouse_polling {
+            return;
+        }
+
+        // Once WM_MOUSELEAVE reported the cursor gone, polling must not bring it back while the
+        // window keeps the focus
+        let mouse_left = self.state.borrow().mouse_left;
+
+        let mut mouse_pos = [-f32::MAX, -f32::MAX];
+        let foreground_hwnd = self.platform.foreground_window();
+        #[cfg(feature = "docking")]
+        let foreground_viewport = unsafe { viewports::is_viewport_window(foreground_hwnd) };
+        #[cfg(not(feature = "docking"))]
+        let foreground_viewport = false;
+        if !mouse_left
+            && (self.hwnd == foreground_hwnd
+                || self.platform.is_child(foreground_hwnd, self.hwnd)
+                || foreground_viewport)
         {
-            io.mouse_pos = [pos.x as f32, pos.y as f32];
+            let pos = match viewports {
+                true => self.platform.cursor_pos(),
+                false => self
+                    .platform
+                    .cursor_pos()
+                    .and_then(|pos| self.platform.screen_to_client(self.hwnd, pos)),
+            };
+            if let Some(pos) = pos {
+                mouse_pos = [pos.x as f32, pos.y as f32];
+            }
+        }
+        io.add_mouse_pos_event(mouse_pos);
+    }
+}
+
+impl<P: Win32Platform> Drop for Win32Impl<P> {
+    fn drop(&mut self) {
+        state::unregister(self.hwnd, &self.state);
+        #[cfg(feature = "docking")]
+        unsafe {
+            viewports::shutdown(&*self.platform, self.context)
         };
     }
 }
 
-#[allow(clippy::missing_safety_doc)]
+/// Feeds a message of `window` to imgui's current context, from the application's window procedure.
+///
+/// Fails with `Win32ImplError::NullIO` when there is no current context. `Win32Impl::handle_message`
+/// is the safe alternative.
+///
+/// Releasing the mouse capture taken for a press sends `WM_CAPTURECHANGED` to `window` before this
+/// returns. That only happens once the message is fed to the IO, so the nested message can come
+/// through here again.
+///
+/// # Safety
+///
+/// Nothing else may hold a reference into the current context's IO during the call, e.g. a
+/// `&mut Context` or `Ui` of the thread that is dispatching this message.
 pub unsafe fn imgui_win32_window_proc(
     window: HWND,
     msg: u32,
     w_param: WPARAM,
     l_param: LPARAM,
+) -> Result<ProcResponse, Win32ImplError> {
+    imgui_win32_window_proc_with_platform(&NativePlatform, window, msg, w_param, l_param)
+}
+
+/// Like `imgui_win32_window_proc`, going through `platform`.
+///
+/// Messages go to imgui's current context and the state `Win32Impl` registered for `window`.
+/// `Win32Impl::handle_message` routes them to an explicit context instead.
+///
+/// # Safety
+///
+/// See `imgui_win32_window_proc`.
+pub unsafe fn imgui_win32_window_proc_with_platform<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
 ) -> Result<ProcResponse, Win32ImplError> {
     let io = match igGetIO().as_mut() {
-        Some(io) => io,
+        Some(io) => Io::from_raw_mut(io),
         None => return Err(Win32ImplError::NullIO),
     };
 
+    let dispatch = window_proc(
+        platform,
+        io,
+        state::lookup(window).as_ref(),
+        window,
+        msg,
+        w_param,
+        l_param,
+    );
+    Ok(dispatch.finish(platform))
+}
+
+// The outcome of `window_proc`, with the work left for after `io` is no longer borrowed
+struct Dispatch {
+    response: ProcResponse,
+    release_capture: bool,
+}
+
+impl Dispatch {
+    // ReleaseCapture sends WM_CAPTURECHANGED before returning, which comes back through the window
+    // procedure and may look up the IO again
+    fn finish<P: Win32Platform>(self, platform: &P) -> ProcResponse {
+        if self.release_capture {
+            platform.release_capture();
+        }
+        self.response
+    }
+}
+
+// `io` must belong to imgui's current context, `state` is the one kept for `window` if any
+unsafe fn window_proc<P: Win32Platform>(
+    platform: &P,
+    io: &mut Io,
+    state: Option<&SharedWindowState>,
+    window: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> Dispatch {
     let w_param = w_param.0 as u32;
+    let mut release_capture = false;
 
-    let result = match msg {
+    let response = match msg {
         WM_LBUTTONDOWN | WM_LBUTTONDBLCLK | WM_RBUTTONDOWN | WM_RBUTTONDBLCLK | WM_MBUTTONDOWN
-        | WM_MBUTTONDBLCLK => {
+        | WM_MBUTTONDBLCLK | WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => {
             let button = match msg {
-                WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => 0,
-                WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => 1,
-                WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => 2,
+                WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => MouseButton::Left,
+                WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => MouseButton::Right,
+                WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => MouseButton::Middle,
                 WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => {
                     if get_xbutton_wparam(w_param) == XBUTTON1 {
-                        3
+                        MouseButton::Extra1
                     } else {
-                        4
+                        MouseButton::Extra2
                     }
                 }
-                _ => 0,
+                _ => MouseButton::Left,
             };
 
-            if !igIsAnyMouseDown() && GetCapture().0 == 0 {
-                SetCapture(window);
-            }
+            update_mouse_source(platform, state);
+            take_capture(platform, window, io, state);
 
-            io.MouseDown[button] = true;
-            ProcResponse::NoAction
+            io.add_mouse_button_event(button, true);
+            ProcResponse::PassThrough
         }
 
         WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP | WM_XBUTTONUP => {
             let button = match msg {
-                WM_LBUTTONUP => 0,
-                WM_RBUTTONUP => 1,
-                WM_MBUTTONUP => 2,
+                WM_LBUTTONUP => MouseButton::Left,
+                WM_RBUTTONUP => MouseButton::Right,
+                WM_MBUTTONUP => MouseButton::Middle,
                 WM_XBUTTONUP => {
                     if get_xbutton_wparam(w_param) == XBUTTON1 {
-                        3
+                        MouseButton::Extra1
                     } else {
-                        4
+                        MouseButton::Extra2
                     }
                 }
-                _ => 0,
+                _ => MouseButton::Left,
+            };
+
+            let source = update_mouse_source(platform, state);
+            io.add_mouse_button_event(button, false);
+            // The wParam of a button-up message carries the buttons that are still held
+            if !any_button_down_wparam(w_param) {
+                release_capture = owns_capture(platform, window, state);
+                if release_capture {
+                    release_mouse_buttons(io);
+                }
+                // A lifted finger doesn't hover, as on the pointer path
+                if source == MouseSource::TouchScreen {
+                    io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+                }
+            }
+            ProcResponse::PassThrough
+        }
+
+        WM_MOUSEMOVE | WM_NCMOUSEMOVE => {
+            let (area, flags) = match msg {
+                WM_MOUSEMOVE => (MouseArea::Client, TME_LEAVE),
+                _ => (MouseArea::NonClient, TME_LEAVE | TME_NONCLIENT),
+            };
+
+            let state = state.cloned().unwrap_or_default();
+            let mut state = state.borrow_mut();
+            // Tracking ends with every WM_MOUSELEAVE, re-arm it when the cursor comes back or
+            // crosses between the client and non-client areas
+            if state.mouse_tracked_area != Some(area) {
+                if state.mouse_tracked_area.is_some() {
+                    platform.track_mouse_event(window, TME_CANCEL);
+                }
+                state.mouse_tracked_area =
+                    platform.track_mouse_event(window, flags).then_some(area);
+            }
+            state.mouse_left = false;
+            state.mouse_source = mouse_source_from_extra_info(platform.message_extra_info());
+            state::set_mouse_window(Some(window));
+
+            let l_param = l_param.0 as u32;
+            let pos = POINT {
+                x: get_x_lparam(l_param),
+                y: get_y_lparam(l_param),
+            };
+            // WM_NCMOUSEMOVE is in screen coordinates, like imgui's mouse with viewports on
+            let pos = match (area, viewports_enabled(io)) {
+                (MouseArea::Client, false) | (MouseArea::NonClient, true) => Some(pos),
+                (MouseArea::Client, true) => platform.client_to_screen(window, pos),
+                (MouseArea::NonClient, false) => platform.screen_to_client(window, pos),
             };
+            if let Some(pos) = pos {
+                io.add_mouse_pos_event([pos.x as f32, pos.y as f32]);
+            }
+            ProcResponse::PassThrough
+        }
 
-            io.MouseDown[button] = false;
-            if !igIsAnyMouseDown() && GetCapture() == window {
-                ReleaseCapture();
+        WM_MOUSELEAVE | WM_NCMOUSELEAVE => {
+            let area = match msg {
+                WM_MOUSELEAVE => MouseArea::Client,
+                _ => MouseArea::NonClient,
+            };
+
+            let state = state.cloned().unwrap_or_default();
+            let mut state = state.borrow_mut();
+            // A leave for an area the cursor already moved out of is stale, e.g. the client area's
+            // WM_MOUSELEAVE arriving after WM_NCMOUSEMOVE
+            if state
+                .mouse_tracked_area
+                .is_none_or(|tracked| tracked == area)
+            {
+                state.mouse_tracked_area = None;
+                // The cursor may have moved on to another viewport's window already
+                if state::mouse_window().is_none_or(|hwnd| hwnd == window) {
+                    state::set_mouse_window(None);
+                    state.mouse_left = true;
+                    io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+                }
             }
-            ProcResponse::NoAction
+            ProcResponse::PassThrough
         }
 
+        WM_POINTERDOWN | WM_POINTERUPDATE | WM_POINTERUP | WM_POINTERLEAVE | WM_POINTERWHEEL
+        | WM_POINTERHWHEEL => match state {
+            Some(state) if state.borrow().pointer_input => pointer::handle_pointer(
+                platform,
+                window,
+                &mut state.borrow_mut(),
+                io,
+                msg,
+                w_param,
+                l_param.0 as u32,
+            ),
+            _ => ProcResponse::PassThrough,
+        },
+
         WM_MOUSEWHEEL => {
-            io.MouseWheel += (get_wheel_delta_wparam(w_param) / WHEEL_DELTA) as f32;
-            ProcResponse::NoAction
+            io.add_mouse_wheel_event([0.0, wheel_notches(w_param)]);
+            ProcResponse::PassThrough
         }
 
         WM_MOUSEHWHEEL => {
-            io.MouseWheelH += (get_wheel_delta_wparam(w_param) / WHEEL_DELTA) as f32;
-            ProcResponse::NoAction
+            // Windows reports tilting right as positive, imgui scrolls left for positive values
+            io.add_mouse_wheel_event([-wheel_notches(w_param), 0.0]);
+            ProcResponse::PassThrough
         }
 
-        WM_KEYDOWN | WM_SYSKEYDOWN => {
-            if w_param < 256 {
-                io.KeysDown[w_param as usize] = true;
+        WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP => {
+            let is_key_down = msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN;
+            let vk = VIRTUAL_KEY(w_param as u16);
+            if w_param < 256 && !keys::is_altgr_control(platform, window, vk, l_param) {
+                // Submit modifiers first so shortcuts see them alongside the key
+                update_key_modifiers(platform, io);
+
+                let vk = resolve_sided_vk(vk, l_param);
+                if let Some(key) = vk_to_imgui_key(vk, l_param) {
+                    io.add_key_event(key, is_key_down);
+                }
             }
-            ProcResponse::NoAction
+            ProcResponse::PassThrough
         }
 
-        WM_KEYUP | WM_SYSKEYUP => {
-            if w_param < 256 {
-                io.KeysDown[w_param as usize] = false;
+        WM_CAPTURECHANGED => {
+            // lParam is the window taking the capture. When it's gone elsewhere the button-up
+            // messages of a drag won't arrive here anymore.
+            if HWND(l_param.0) != window {
+                if let Some(state) = state {
+                    state.borrow_mut().mouse_captured = false;
+                }
+                release_mouse_buttons(io);
             }
-            ProcResponse::NoAction
+            ProcResponse::PassThrough
+        }
+
+        WM_SETFOCUS => {
+            ImGuiIO_AddFocusEvent(io.raw_mut(), true);
+            ProcResponse::PassThrough
+        }
+
+        WM_KILLFOCUS => {
+            release_capture = release_held_input(platform, window, io, state);
+            ImGuiIO_AddFocusEvent(io.raw_mut(), false);
+            ProcResponse::PassThrough
+        }
+
+        WM_ACTIVATEAPP => {
+            let activated = w_param != 0;
+            if !activated {
+                release_capture = release_held_input(platform, window, io, state);
+            }
+            ImGuiIO_AddFocusEvent(io.raw_mut(), activated);
+            ProcResponse::PassThrough
         }
 
         WM_CHAR => {
-            if w_param > 0 && w_param < 0x10000 {
-                let ig_io = igGetIO();
-                ImGuiIO_AddInputCharacterUTF16(ig_io, w_param as u16);
+            match state {
+                Some(state) => {
+                    text::handle_char(platform, window, &mut state.borrow_mut(), io, w_param)
+                }
+                // Without a `Win32Impl` for this window there is nowhere to keep partial
+                // characters, let imgui buffer surrogates itself
+                None if platform.is_window_unicode(window) && w_param > 0 && w_param < 0x10000 => {
+                    ImGuiIO_AddInputCharacterUTF16(io.raw_mut(), w_param as u16)
+                }
+                None => {
+                    text::handle_char(platform, window, &mut WindowState::default(), io, w_param)
+                }
             }
-            ProcResponse::NoAction
+            ProcResponse::PassThrough
         }
 
+        WM_UNICHAR => text::handle_unichar(io, w_param),
+
         WM_SETCURSOR => {
-            if loword(l_param.0 as u32) as u32 == HTCLIENT && update_cursor() {
-                ProcResponse::ActionTaken
+            if loword(l_param.0 as u32) as u32 == HTCLIENT && update_cursor(platform, io, state) {
+                ProcResponse::Handled(LRESULT(1))
             } else {
-                ProcResponse::NoAction
+                ProcResponse::PassThrough
             }
         }
 
-        WM_DEVICECHANGE => ProcResponse::NoAction,
-        _ => ProcResponse::NoAction,
+        WM_DPICHANGED => {
+            // Both words of wParam carry the new DPI, the X and Y values are always equal
+            if let Some(state) = state {
+                let mut state = state.borrow_mut();
+                state.dpi = loword(w_param) as u32;
+                state.dpi_changed = true;
+            }
+            ProcResponse::PassThrough
+        }
+
+        WM_DISPLAYCHANGE => {
+            if let Some(state) = state {
+                state.borrow_mut().displays_changed = true;
+            }
+            ProcResponse::PassThrough
+        }
+
+        WM_DEVICECHANGE => {
+            if w_param == DBT_DEVNODES_CHANGED {
+                if let Some(state) = state {
+                    state.borrow_mut().devices_changed = true;
+                }
+            }
+            ProcResponse::PassThrough
+        }
+        _ => ProcResponse::PassThrough,
     };
 
-    Ok(result)
+    let response = match response {
+        ProcResponse::PassThrough => capture_response(io, msg),
+        response => response,
+    };
+    Dispatch {
+        response,
+        release_capture,
+    }
 }
 
-unsafe fn update_cursor() -> bool {
-    let io = match igGetIO().as_mut() {
-        Some(io) => io,
-        None => return false,
+fn update_mouse_source<P: Win32Platform>(
+    platform: &P,
+    state: Option<&SharedWindowState>,
+) -> MouseSource {
+    let source = mouse_source_from_extra_info(platform.message_extra_info());
+    if let Some(state) = state {
+        state.borrow_mut().mouse_source = source;
+    }
+    source
+}
+
+// Once focus is gone the matching WM_KEYUP / WM_*BUTTONUP messages never arrive, so release
+// everything that may still be held. imgui drops events that don't change a key's state.
+// Returns whether the capture taken for a press is to be released as well.
+fn release_held_input<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    io: &mut Io,
+    state: Option<&SharedWindowState>,
+) -> bool {
+    for key in Key::VARIANTS {
+        if (key as ImGuiKey) < ImGuiKey_MouseLeft {
+            io.add_key_event(key, false);
+        }
+    }
+    for key in [Key::ModCtrl, Key::ModShift, Key::ModAlt, Key::ModSuper] {
+        io.add_key_event(key, false);
+    }
+    release_mouse_buttons(io);
+    owns_capture(platform, window, state)
+}
+
+fn release_mouse_buttons(io: &mut Io) {
+    for button in MouseButton::VARIANTS {
+        io.add_mouse_button_event(button, false);
+    }
+}
+
+// Windows without a `Win32Impl` always capture the mouse for presses
+fn take_capture<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    io: &Io,
+    state: Option<&SharedWindowState>,
+) {
+    let policy = state.map_or(CapturePolicy::Always, |state| state.borrow().capture_policy);
+    let take = match policy {
+        CapturePolicy::Always => true,
+        CapturePolicy::OnlyWhenImguiWantsMouse => io.want_capture_mouse,
+        CapturePolicy::Never => false,
+    };
+    if take && platform.capture().0 == 0 {
+        platform.set_capture(window);
+        if let Some(state) = state {
+            state.borrow_mut().mouse_captured = true;
+        }
+    }
+}
+
+// Only a capture taken for a press is released, not one the application holds
+fn owns_capture<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    state: Option<&SharedWindowState>,
+) -> bool {
+    let captured = match state {
+        Some(state) => std::mem::take(&mut state.borrow_mut().mouse_captured),
+        None => true,
     };
+    captured && platform.capture() == window
+}
 
-    if io.ConfigFlags & ImGuiConfigFlags_NoMouseCursorChange as i32 != 0 {
+// Windows without a `Win32Impl` get the system cursors
+unsafe fn update_cursor<P: Win32Platform>(
+    platform: &P,
+    io: &Io,
+    state: Option<&SharedWindowState>,
+) -> bool {
+    if io
+        .config_flags
+        .contains(ConfigFlags::NO_MOUSE_CURSOR_CHANGE)
+    {
         return false;
     };
 
-    let mouse_cursor = igGetMouseCursor();
-    let win32_cursor = if mouse_cursor == ImGuiMouseCursor_None || io.MouseDrawCursor {
-        HCURSOR(0)
-    } else {
-        #[allow(non_upper_case_globals)]
-        HCURSOR(
-            match mouse_cursor {
-                ImGuiMouseCursor_Arrow => IDC_ARROW,
-                ImGuiMouseCursor_TextInput => IDC_IBEAM,
-                ImGuiMouseCursor_ResizeAll => IDC_SIZEALL,
-                ImGuiMouseCursor_ResizeEW => IDC_SIZEWE,
-                ImGuiMouseCursor_ResizeNS => IDC_SIZENS,
-                ImGuiMouseCursor_ResizeNESW => IDC_SIZENESW,
-                ImGuiMouseCursor_ResizeNWSE => IDC_SIZENWSE,
-                ImGuiMouseCursor_Hand => IDC_HAND,
-                ImGuiMouseCursor_NotAllowed => IDC_NO,
-                _ => IDC_ARROW,
-            }
-            .0 as isize,
-        )
+    let mouse_cursor = match io.mouse_draw_cursor {
+        true => RequestedCursor::Hidden,
+        false => RequestedCursor::from_raw(igGetMouseCursor()),
+    };
+    let win32_cursor = match state {
+        Some(state) => state.borrow_mut().cursors.resolve(platform, mouse_cursor),
+        None => CursorMap::default().resolve(platform, mouse_cursor),
     };
 
-    SetCursor(win32_cursor);
+    platform.set_cursor(win32_cursor);
     true
 }
 
@@ -309,4 +1051,318 @@ pub enum Win32ImplError {
     ExternalError(String),
     #[error("Could not get IO, reference was null")]
     NullIO,
+    #[error("{0:?} is not a window")]
+    InvalidWindow(HWND),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::cell::{Cell, RefCell};
+
+    #[test]
+    fn wheel_deltas_are_signed_and_fractional() {
+        assert_eq!(wheel_notches(wheel_wparam(120) as u32), 1.0);
+        assert_eq!(wheel_notches(wheel_wparam(-120) as u32), -1.0);
+        assert_eq!(wheel_notches(wheel_wparam(-360) as u32), -3.0);
+        assert_eq!(wheel_notches(wheel_wparam(30) as u32), 0.25);
+        assert_eq!(wheel_notches(wheel_wparam(-12) as u32), -0.1);
+        assert_eq!(wheel_notches(wheel_wparam(0) as u32), 0.0);
+    }
+
+    #[test]
+    fn prepare_frame_reads_display_size_and_mouse() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_mouse_polling(true);
+        backend
+            .platform()
+            .move_cursor(Some(POINT { x: 130, y: 70 }));
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(ctx.io().display_size, [800.0, 600.0]);
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [30.0, 20.0]);
+    }
+
+    #[test]
+    fn dpi_scale_follows_dpi_changes() {
+        let platform = mock_platform();
+        platform.set_dpi(144);
+        let (_guard, mut ctx, mut backend) = setup_with(platform);
+        assert_eq!(backend.dpi_scale(), 1.5);
+
+        let scales = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen = scales.clone();
+        backend.set_dpi_changed_callback(move |_, scale| seen.borrow_mut().push(scale));
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(scales.borrow().is_empty());
+        assert_eq!(ctx.io().display_framebuffer_scale, [1.0, 1.0]);
+
+        send(backend.platform(), WM_DPICHANGED, 192 << 16 | 192, 0);
+        assert_eq!(backend.dpi_scale(), 2.0);
+        backend.prepare_frame(&mut ctx).unwrap();
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(*scales.borrow(), [2.0]);
+    }
+
+    #[test]
+    fn handled_messages_answer_what_win32_expects() {
+        let (_guard, mut ctx, mut backend) = setup();
+        let mut answer = |msg, w_param| {
+            backend
+                .handle_message(&mut ctx, HWND_MAIN, msg, WPARAM(w_param), LPARAM(0))
+                .lresult()
+        };
+
+        assert_eq!(
+            answer(WM_UNICHAR, UNICODE_NOCHAR as usize),
+            Some(LRESULT(1))
+        );
+        assert_eq!(answer(WM_UNICHAR, 'z' as usize), Some(LRESULT(0)));
+        assert_eq!(answer(WM_CHAR, 'z' as usize), None);
+    }
+
+    #[test]
+    fn handle_message_uses_the_instance_state() {
+        let (_guard, mut ctx) = context();
+        let mut first =
+            Win32Impl::init_with_platform(&mut ctx, HWND_MAIN, mock_platform()).unwrap();
+        // Takes over the window's registry entry
+        let second = Win32Impl::init_with_platform(&mut ctx, HWND_MAIN, mock_platform()).unwrap();
+
+        let dpi_changed = WPARAM(192 << 16 | 192);
+        first.handle_message(&mut ctx, HWND_MAIN, WM_DPICHANGED, dpi_changed, LPARAM(0));
+        assert_eq!(first.dpi_scale(), 2.0);
+        assert_eq!(second.dpi_scale(), 1.0);
+
+        first.handle_message(
+            &mut ctx,
+            HWND_MAIN,
+            WM_MOUSEWHEEL,
+            WPARAM(wheel_wparam(120)),
+            LPARAM(0),
+        );
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_wheel, 1.0);
+    }
+
+    // An application keeping its backend in a `RefCell`, which is borrowed while a message is
+    // handled
+    struct Host {
+        app: RefCell<(Win32Impl<MockPlatform>, Context)>,
+        nested: Cell<u32>,
+    }
+
+    unsafe extern "system" fn host_proc(
+        hwnd: HWND,
+        msg: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+        _id: usize,
+        data: usize,
+    ) -> LRESULT {
+        let host = &*(data as *const Host);
+        match host.app.try_borrow_mut() {
+            Ok(mut app) => {
+                let (backend, ctx) = &mut *app;
+                backend
+                    .handle_message(ctx, hwnd, msg, w_param, l_param)
+                    .into_lresult(|| LRESULT(0))
+            }
+            Err(_) => {
+                host.nested.set(host.nested.get() + 1);
+                LRESULT(0)
+            }
+        }
+    }
+
+    #[test]
+    fn handle_message_releases_the_capture_after_handling_the_message() {
+        let (_guard, ctx, backend) = setup();
+        let host = Host {
+            app: RefCell::new((backend, ctx)),
+            nested: Cell::new(0),
+        };
+        let data = &host as *const Host as usize;
+        let captured =
+            |app: &(Win32Impl<MockPlatform>, Context)| app.0.platform().captured_window();
+        host.app
+            .borrow()
+            .0
+            .platform()
+            .set_window_subclass(HWND_MAIN, Some(host_proc), 1, data);
+        let dispatch = |msg, w_param| {
+            let mut app = host.app.borrow_mut();
+            let (backend, ctx) = &mut *app;
+            backend.handle_message(ctx, HWND_MAIN, msg, WPARAM(w_param), LPARAM(0));
+        };
+
+        dispatch(WM_LBUTTONDOWN, MK_LBUTTON.0 as usize);
+        assert_eq!(captured(&host.app.borrow()), HWND_MAIN);
+        {
+            let ctx = &mut host.app.borrow_mut().1;
+            assert!(ctx.new_frame().is_mouse_down(MouseButton::Left));
+            ctx.render();
+        }
+
+        // ReleaseCapture sends WM_CAPTURECHANGED while the host is still borrowed
+        dispatch(WM_LBUTTONUP, 0);
+        assert_eq!(host.nested.get(), 1);
+        assert_eq!(captured(&host.app.borrow()), HWND(0));
+
+        let ctx = &mut host.app.borrow_mut().1;
+        assert!(!ctx.new_frame().is_mouse_down(MouseButton::Left));
+    }
+
+    #[test]
+    fn responses_convert_to_lresults() {
+        let default_proc = || LRESULT(42);
+        assert_eq!(ProcResponse::PassThrough.lresult(), None);
+        assert_eq!(
+            ProcResponse::PassThrough.into_lresult(default_proc),
+            LRESULT(42)
+        );
+        assert_eq!(ProcResponse::Consumed(Some(LRESULT(1))).lresult(), None);
+        assert_eq!(
+            ProcResponse::Consumed(Some(LRESULT(1))).into_lresult(default_proc),
+            LRESULT(42)
+        );
+        assert_eq!(
+            ProcResponse::Handled(LRESULT(1)).lresult(),
+            Some(LRESULT(1))
+        );
+        assert_eq!(
+            ProcResponse::Handled(LRESULT(0)).into_lresult(|| unreachable!()),
+            LRESULT(0)
+        );
+    }
+
+    #[test]
+    fn input_imgui_wants_is_consumed() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+        let key = VK_A.0 as usize;
+
+        assert_eq!(
+            send(platform, WM_LBUTTONDOWN, MK_LBUTTON.0 as usize, 0),
+            ProcResponse::PassThrough
+        );
+        assert_eq!(
+            send(platform, WM_KEYDOWN, key, 0),
+            ProcResponse::PassThrough
+        );
+
+        ctx.io_mut().want_capture_mouse = true;
+        assert_eq!(
+            send(platform, WM_LBUTTONUP, 0, 0),
+            ProcResponse::Consumed(None)
+        );
+        assert_eq!(
+            send(platform, WM_MOUSEWHEEL, wheel_wparam(120), 0),
+            ProcResponse::Consumed(None)
+        );
+        assert_eq!(
+            send(platform, WM_XBUTTONDOWN, (XBUTTON1.0 << 16) as usize, 0),
+            ProcResponse::Consumed(Some(LRESULT(1)))
+        );
+        assert_eq!(
+            send(platform, WM_KEYDOWN, key, 0),
+            ProcResponse::PassThrough
+        );
+        assert_eq!(
+            send(platform, WM_CHAR, 'a' as usize, 0),
+            ProcResponse::PassThrough
+        );
+        assert_eq!(send(platform, WM_SIZE, 0, 0), ProcResponse::PassThrough);
+
+        ctx.io_mut().want_text_input = true;
+        assert_eq!(
+            send(platform, WM_KEYDOWN, key, 0),
+            ProcResponse::PassThrough
+        );
+        assert_eq!(
+            send(platform, WM_CHAR, 'a' as usize, 0),
+            ProcResponse::Consumed(None)
+        );
+
+        ctx.io_mut().want_capture_keyboard = true;
+        assert_eq!(
+            send(platform, WM_KEYDOWN, key, 0),
+            ProcResponse::Consumed(None)
+        );
+        assert_eq!(
+            send(platform, WM_SYSKEYUP, VK_MENU.0 as usize, 0),
+            ProcResponse::Consumed(None)
+        );
+    }
+
+    #[test]
+    fn dropping_the_backend_forgets_window_state() {
+        let (_guard, _ctx, backend) = setup();
+        assert!(state::lookup(HWND_MAIN).is_some());
+
+        drop(backend);
+        assert!(state::lookup(HWND_MAIN).is_none());
+    }
+
+    #[test]
+    fn invalid_windows_are_rejected() {
+        let (_guard, mut ctx, mut backend) = setup();
+        assert!(matches!(
+            Win32Impl::init_with_platform(&mut ctx, HWND(0), mock_platform()),
+            Err(Win32ImplError::InvalidWindow(HWND(0)))
+        ));
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        backend.platform().remove_window(HWND_MAIN);
+        assert!(matches!(
+            backend.prepare_frame(&mut ctx),
+            Err(Win32ImplError::InvalidWindow(HWND_MAIN))
+        ));
+    }
+
+    #[test]
+    fn focus_messages_queue_focus_events() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(platform, WM_KILLFOCUS, 0, 0);
+        let ui = ctx.new_frame();
+        assert!(ui.io().app_focus_lost);
+        ctx.render();
+
+        send(platform, WM_SETFOCUS, 0, 0);
+        let ui = ctx.new_frame();
+        assert!(!ui.io().app_focus_lost);
+    }
+
+    #[test]
+    fn focus_loss_releases_held_input() {
+        for (msg, w_param) in [(WM_KILLFOCUS, 0), (WM_ACTIVATEAPP, 0)] {
+            let (_guard, mut ctx, backend) = setup();
+            let platform = backend.platform();
+
+            platform.set_key_down(VK_LSHIFT, true);
+            send(platform, WM_KEYDOWN, VK_SHIFT.0 as usize, 0x2a << 16);
+            send(platform, WM_KEYDOWN, VK_A.0 as usize, 0);
+            send(platform, WM_LBUTTONDOWN, MK_LBUTTON.0 as usize, 0);
+            let ui = ctx.new_frame();
+            assert!(ui.is_key_down(Key::A));
+            assert!(ui.is_mouse_down(MouseButton::Left));
+            ctx.render();
+
+            send(platform, msg, w_param, 0);
+            assert_eq!(platform.captured_window(), HWND(0));
+
+            let ui = ctx.new_frame();
+            assert!(!ui.is_key_down(Key::A));
+            assert!(!ui.is_key_down(Key::LeftShift));
+            assert!(!ui.is_mouse_down(MouseButton::Left));
+            assert!(!ui.io().key_shift);
+            assert!(ui.io().app_focus_lost);
+        }
+    }
 }
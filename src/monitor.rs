@@ -0,0 +1,81 @@
+use imgui::Context;
+use windows::Win32::Foundation::RECT;
+
+/// A display attached to the desktop, as reported by `EnumDisplayMonitors`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorInfo {
+    /// Bounds on the virtual desktop, in screen coordinates.
+    pub rect: RECT,
+    /// `rect` minus the task bar and docked toolbars.
+    pub work_rect: RECT,
+    /// `1.0` at 96 DPI.
+    pub dpi_scale: f32,
+    pub primary: bool,
+}
+
+/// Hands the monitors to imgui, which keeps popups, tooltips and viewports on screen with them.
+///
+/// Only the docking branch of imgui has a platform IO to hold them, without the `docking` feature
+/// they're just kept by `Win32Impl`.
+#[cfg(feature = "docking")]
+pub(crate) fn update_platform_io(imgui: &mut Context, monitors: &[MonitorInfo]) {
+    let monitors: Vec<imgui::PlatformMonitor> = monitors.iter().map(Into::into).collect();
+    imgui
+        .platform_io_mut()
+        .monitors
+        .replace_from_slice(&monitors);
+}
+
+#[cfg(not(feature = "docking"))]
+pub(crate) fn update_platform_io(_imgui: &mut Context, _monitors: &[MonitorInfo]) {}
+
+#[cfg(feature = "docking")]
+impl From<&MonitorInfo> for imgui::PlatformMonitor {
+    fn from(monitor: &MonitorInfo) -> Self {
+        let pos = |rect: &RECT| [rect.left as f32, rect.top as f32];
+        let size = |rect: &RECT| {
+            [
+                (rect.right - rect.left) as f32,
+                (rect.bottom - rect.top) as f32,
+            ]
+        };
+        imgui::PlatformMonitor {
+            main_pos: pos(&monitor.rect),
+            main_size: size(&monitor.rect),
+            work_pos: pos(&monitor.work_rect),
+            work_size: size(&monitor.work_rect),
+            dpi_scale: monitor.dpi_scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::*;
+    use windows::Win32::UI::WindowsAndMessaging::WM_DISPLAYCHANGE;
+
+    #[test]
+    fn monitors_are_enumerated_again_after_display_changes() {
+        let platform = mock_platform();
+        platform.set_monitors(vec![monitor_at(0, true)]);
+        let (_guard, mut ctx, mut backend) = setup_with(platform);
+        assert_eq!(backend.monitors(), [monitor_at(0, true)]);
+
+        let monitors = [monitor_at(0, true), monitor_at(-1920, false)];
+        backend.platform().set_monitors(monitors.to_vec());
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(backend.monitors(), [monitor_at(0, true)]);
+
+        send(backend.platform(), WM_DISPLAYCHANGE, 32, 1080 << 16 | 1920);
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(backend.monitors(), monitors);
+
+        #[cfg(feature = "docking")]
+        {
+            let platform_monitors = ctx.platform_io_mut().monitors.as_slice();
+            assert_eq!(platform_monitors.len(), 2);
+            assert_eq!(platform_monitors[1].main_pos, [-1920.0, 0.0]);
+            assert_eq!(platform_monitors[1].work_size, [1920.0, 1040.0]);
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use windows::Win32::Foundation::LPARAM;
+
+// `GetMessageExtraInfo` of mouse messages synthesized from touch and pen input carries the
+// `MI_WP_SIGNATURE` signature, with bit 7 set for touch.
+const MI_WP_SIGNATURE: u32 = 0xff515700;
+const SIGNATURE_MASK: u32 = 0xffffff80;
+const MI_WP_SIGNATURE_TOUCH: u32 = MI_WP_SIGNATURE | 0x80;
+
+/// The device a mouse message originated from.
+///
+/// imgui 1.89.2, which imgui-rs 0.12 binds, predates `io.AddMouseSourceEvent`, so the source is only
+/// reported through `Win32Impl::mouse_source` and isn't forwarded to imgui.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MouseSource {
+    #[default]
+    Mouse,
+    TouchScreen,
+    Pen,
+}
+
+/// When the window takes the mouse capture on a button press, so drags keep reporting the mouse
+/// outside of it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CapturePolicy {
+    #[default]
+    Always,
+    /// Only for presses over imgui, going by `want_capture_mouse` of the last frame. Drags in the
+    /// rest of the window are left to the application.
+    OnlyWhenImguiWantsMouse,
+    /// The application manages the capture itself.
+    Never,
+}
+
+/// Tells the source of a mouse message from its `GetMessageExtraInfo` value.
+pub fn mouse_source_from_extra_info(extra_info: LPARAM) -> MouseSource {
+    match extra_info.0 as u32 & SIGNATURE_MASK {
+        MI_WP_SIGNATURE_TOUCH => MouseSource::TouchScreen,
+        MI_WP_SIGNATURE => MouseSource::Pen,
+        _ => MouseSource::Mouse,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        imgui_win32_window_proc_with_platform, test_util::*, CapturePolicy, MockPlatform,
+        ProcResponse, Win32Platform,
+    };
+    use imgui::MouseButton;
+    use windows::Win32::{
+        Foundation::{HWND, LRESULT, POINT, WPARAM},
+        System::SystemServices::{
+            MK_LBUTTON, MK_RBUTTON, MK_XBUTTON1, MK_XBUTTON2, MODIFIERKEYS_FLAGS,
+        },
+        UI::{Controls::WM_MOUSELEAVE, Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
+    };
+
+    #[test]
+    fn extra_info_signatures() {
+        for (extra_info, source) in [
+            (0u32, MouseSource::Mouse),
+            (0x1234, MouseSource::Mouse),
+            (0xff515700, MouseSource::Pen),
+            (0xff515701, MouseSource::Pen),
+            (0xff515780, MouseSource::TouchScreen),
+            (0xff5157ff, MouseSource::TouchScreen),
+            (0xff515600, MouseSource::Mouse),
+        ] {
+            assert_eq!(
+                mouse_source_from_extra_info(LPARAM(extra_info as isize)),
+                source,
+                "{extra_info:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn wheel_messages_queue_wheel_events() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(platform, WM_MOUSEWHEEL, wheel_wparam(-60), 0);
+        send(platform, WM_MOUSEHWHEEL, wheel_wparam(240), 0);
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_wheel, -0.5);
+        assert_eq!(ui.io().mouse_wheel_h, -2.0);
+    }
+
+    #[test]
+    fn prepare_frame_only_polls_mouse_when_enabled() {
+        let (_guard, mut ctx, mut backend) = setup();
+        assert!(!backend.mouse_polling());
+        backend
+            .platform()
+            .move_cursor(Some(POINT { x: 130, y: 70 }));
+
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(5, 6));
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [5.0, 6.0]);
+    }
+
+    #[test]
+    fn mouse_moves_queue_every_position() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(platform, WM_MOUSEMOVE, 0, mouse_lparam(10, 20));
+        send(
+            platform,
+            WM_LBUTTONDOWN,
+            MK_LBUTTON.0 as usize,
+            mouse_lparam(10, 20),
+        );
+        send(platform, WM_MOUSEMOVE, 0, mouse_lparam(-15, 40));
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [10.0, 20.0]);
+        assert!(ui.is_mouse_down(MouseButton::Left));
+        ctx.render();
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [-15.0, 40.0]);
+    }
+
+    #[test]
+    fn non_client_moves_are_converted_from_screen_coordinates() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(
+            platform,
+            WM_NCMOUSEMOVE,
+            HTCAPTION as usize,
+            mouse_lparam(-20, 30),
+        );
+        assert_eq!(
+            platform.mouse_tracking_requests(),
+            [(HWND_MAIN, TME_LEAVE | TME_NONCLIENT)]
+        );
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [-120.0, -20.0]);
+    }
+
+    #[test]
+    fn mouse_leave_hides_mouse_until_it_moves_back() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_mouse_polling(true);
+        backend
+            .platform()
+            .move_cursor(Some(POINT { x: 130, y: 70 }));
+
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        assert_eq!(
+            backend.platform().mouse_tracking_requests(),
+            [(HWND_MAIN, TME_LEAVE)]
+        );
+
+        send(backend.platform(), WM_MOUSELEAVE, 0, 0);
+        backend.prepare_frame(&mut ctx).unwrap();
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [-f32::MAX, -f32::MAX]);
+        ctx.render();
+
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        assert_eq!(backend.platform().mouse_tracking_requests().len(), 2);
+        backend.prepare_frame(&mut ctx).unwrap();
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [30.0, 20.0]);
+    }
+
+    #[test]
+    fn mouse_source_follows_the_last_mouse_message() {
+        let (_guard, _ctx, backend) = setup();
+        assert_eq!(backend.mouse_source(), MouseSource::Mouse);
+
+        backend
+            .platform()
+            .set_message_extra_info(LPARAM(0xff515780u32 as isize));
+        send(backend.platform(), WM_LBUTTONDOWN, MK_LBUTTON.0 as usize, 0);
+        assert_eq!(backend.mouse_source(), MouseSource::TouchScreen);
+
+        backend
+            .platform()
+            .set_message_extra_info(LPARAM(0xff515700u32 as isize));
+        send(backend.platform(), WM_MOUSEMOVE, 0, 0);
+        assert_eq!(backend.mouse_source(), MouseSource::Pen);
+
+        backend.platform().set_message_extra_info(LPARAM(0));
+        send(backend.platform(), WM_LBUTTONUP, 0, 0);
+        assert_eq!(backend.mouse_source(), MouseSource::Mouse);
+    }
+
+    #[test]
+    fn lifted_touch_stops_hovering() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+        platform.set_message_extra_info(LPARAM(MI_WP_SIGNATURE_TOUCH as isize));
+
+        send(platform, WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        send(
+            platform,
+            WM_LBUTTONDOWN,
+            MK_LBUTTON.0 as usize,
+            mouse_lparam(30, 20),
+        );
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [30.0, 20.0]);
+        assert!(ui.is_mouse_down(MouseButton::Left));
+        ctx.render();
+
+        send(platform, WM_LBUTTONUP, 0, mouse_lparam(30, 20));
+        // The release still lands where the finger was
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [30.0, 20.0]);
+        assert!(!ui.is_mouse_down(MouseButton::Left));
+        ctx.render();
+        assert_eq!(ctx.new_frame().io().mouse_pos, [-f32::MAX, -f32::MAX]);
+        ctx.render();
+
+        // A mouse button-up leaves the cursor where it is
+        platform.set_message_extra_info(LPARAM(0));
+        send(platform, WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        send(
+            platform,
+            WM_LBUTTONDOWN,
+            MK_LBUTTON.0 as usize,
+            mouse_lparam(30, 20),
+        );
+        send(platform, WM_LBUTTONUP, 0, mouse_lparam(30, 20));
+        for _ in 0..2 {
+            assert_eq!(ctx.new_frame().io().mouse_pos, [30.0, 20.0]);
+            ctx.render();
+        }
+    }
+
+    #[test]
+    fn stale_client_leave_is_ignored_in_the_non_client_area() {
+        let (_guard, mut ctx, backend) = setup();
+
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        send(backend.platform(), WM_NCMOUSEMOVE, 0, mouse_lparam(140, 45));
+        send(backend.platform(), WM_MOUSELEAVE, 0, 0);
+        assert_eq!(
+            backend.platform().mouse_tracking_requests(),
+            [
+                (HWND_MAIN, TME_LEAVE),
+                (HWND_MAIN, TME_CANCEL),
+                (HWND_MAIN, TME_LEAVE | TME_NONCLIENT),
+            ]
+        );
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [40.0, -5.0]);
+    }
+
+    #[test]
+    fn leave_after_moving_to_another_window_is_ignored() {
+        let (_guard, mut ctx, backend) = setup();
+        let other = HWND(0x2000);
+
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(30, 20));
+        unsafe {
+            imgui_win32_window_proc_with_platform(
+                backend.platform(),
+                other,
+                WM_MOUSEMOVE,
+                WPARAM(0),
+                LPARAM(mouse_lparam(5, 6)),
+            )
+        }
+        .unwrap();
+        send(backend.platform(), WM_MOUSELEAVE, 0, 0);
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [5.0, 6.0]);
+    }
+
+    #[test]
+    fn prepare_frame_hides_mouse_when_not_foreground() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_mouse_polling(true);
+        backend.platform().set_foreground_window(HWND(0x2000));
+        backend
+            .platform()
+            .move_cursor(Some(POINT { x: 130, y: 70 }));
+
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [-f32::MAX, -f32::MAX]);
+    }
+
+    #[test]
+    fn mouse_buttons_take_and_release_capture() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(platform, WM_LBUTTONDOWN, MK_LBUTTON.0 as usize, 0);
+        assert_eq!(platform.captured_window(), HWND_MAIN);
+        send(
+            platform,
+            WM_RBUTTONDOWN,
+            (MK_LBUTTON | MK_RBUTTON).0 as usize,
+            0,
+        );
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_mouse_down(MouseButton::Left));
+        assert!(ui.is_mouse_down(MouseButton::Right));
+
+        send(platform, WM_LBUTTONUP, MK_RBUTTON.0 as usize, 0);
+        assert_eq!(platform.captured_window(), HWND_MAIN);
+        send(platform, WM_RBUTTONUP, 0, 0);
+        assert_eq!(platform.captured_window(), HWND(0));
+    }
+
+    #[test]
+    fn capture_follows_the_capture_policy() {
+        let (_guard, mut ctx, mut backend) = setup();
+        let click = |platform: &MockPlatform| {
+            send(platform, WM_LBUTTONDOWN, MK_LBUTTON.0 as usize, 0);
+            let captured = platform.captured_window();
+            send(platform, WM_LBUTTONUP, 0, 0);
+            captured
+        };
+
+        assert_eq!(backend.capture_policy(), CapturePolicy::Always);
+        assert_eq!(click(backend.platform()), HWND_MAIN);
+        assert_eq!(backend.platform().captured_window(), HWND(0));
+
+        // A capture the application took is left to it
+        backend.platform().set_capture(HWND_MAIN);
+        click(backend.platform());
+        assert_eq!(backend.platform().captured_window(), HWND_MAIN);
+        backend.platform().release_capture();
+
+        backend.set_capture_policy(CapturePolicy::Never);
+        assert_eq!(click(backend.platform()), HWND(0));
+
+        backend.set_capture_policy(CapturePolicy::OnlyWhenImguiWantsMouse);
+        assert_eq!(click(backend.platform()), HWND(0));
+        ctx.io_mut().want_capture_mouse = true;
+        assert_eq!(click(backend.platform()), HWND_MAIN);
+        assert_eq!(backend.platform().captured_window(), HWND(0));
+    }
+
+    #[test]
+    fn lost_capture_releases_buttons() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        send(platform, WM_LBUTTONDOWN, MK_LBUTTON.0 as usize, 0);
+        send(platform, WM_CAPTURECHANGED, 0, HWND_MAIN.0);
+        assert!(ctx.new_frame().is_mouse_down(MouseButton::Left));
+        ctx.render();
+
+        platform.set_capture(HWND(0x3000));
+        send(platform, WM_CAPTURECHANGED, 0, 0x3000);
+        assert!(!ctx.new_frame().is_mouse_down(MouseButton::Left));
+
+        // The capture isn't this window's to release anymore
+        send(platform, WM_LBUTTONUP, 0, 0);
+        assert_eq!(platform.captured_window(), HWND(0x3000));
+    }
+
+    #[test]
+    fn side_buttons_are_pressed_and_released() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+        let xbutton = |button: MOUSEHOOKSTRUCTEX_MOUSE_DATA, held: MODIFIERKEYS_FLAGS| {
+            ((button.0 << 16) | held.0) as usize
+        };
+
+        // Back and forward clicks are the application's unless imgui wants the mouse
+        let response = send(platform, WM_XBUTTONDOWN, xbutton(XBUTTON1, MK_XBUTTON1), 0);
+        assert_eq!(response, ProcResponse::PassThrough);
+        assert_eq!(platform.captured_window(), HWND_MAIN);
+        let response = send(
+            platform,
+            WM_XBUTTONDBLCLK,
+            xbutton(XBUTTON2, MK_XBUTTON1 | MK_XBUTTON2),
+            0,
+        );
+        assert_eq!(response, ProcResponse::PassThrough);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_mouse_down(MouseButton::Extra1));
+        assert!(ui.is_mouse_down(MouseButton::Extra2));
+        ctx.render();
+
+        ctx.io_mut().want_capture_mouse = true;
+        let response = send(platform, WM_XBUTTONUP, xbutton(XBUTTON1, MK_XBUTTON2), 0);
+        assert_eq!(response, ProcResponse::Consumed(Some(LRESULT(1))));
+        assert_eq!(platform.captured_window(), HWND_MAIN);
+        send(
+            platform,
+            WM_XBUTTONUP,
+            xbutton(XBUTTON2, MODIFIERKEYS_FLAGS(0)),
+            0,
+        );
+        assert_eq!(platform.captured_window(), HWND(0));
+    }
+}
@@ -0,0 +1,1210 @@
+use crate::{MonitorInfo, Win32ImplError};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::OnceLock;
+#[cfg(feature = "docking")]
+use windows::{core::HSTRING, Win32::Foundation::COLORREF};
+use windows::{
+    core::PCWSTR,
+    s, w,
+    Win32::{
+        Foundation::{GetLastError, BOOL, HANDLE, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
+        Globalization::{
+            GetLocaleInfoW, IsDBCSLeadByteEx, MultiByteToWideChar, CP_ACP,
+            LOCALE_IDEFAULTANSICODEPAGE, LOCALE_RETURN_NUMBER, MB_PRECOMPOSED,
+        },
+        Graphics::Gdi::{
+            ClientToScreen, EnumDisplayMonitors, GetDC, GetDeviceCaps, GetMonitorInfoW,
+            MonitorFromWindow, ReleaseDC, ScreenToClient, HDC, HMONITOR, LOGPIXELSX, MONITORINFO,
+            MONITOR_DEFAULTTONEAREST,
+        },
+        System::{
+            DataExchange::{
+                CloseClipboard, EmptyClipboard, GetClipboardData, OpenClipboard, SetClipboardData,
+            },
+            LibraryLoader::{GetModuleHandleW, GetProcAddress, LoadLibraryW},
+            Memory::{
+                GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+            },
+            SystemServices::CF_UNICODETEXT,
+        },
+        UI::{
+            HiDpi::{MDT_EFFECTIVE_DPI, MONITOR_DPI_TYPE},
+            Input::{
+                KeyboardAndMouse::*,
+                Pointer::{
+                    GetPointerInfo, GetPointerPenInfo, GetPointerType, POINTER_FLAGS, POINTER_INFO,
+                    POINTER_PEN_INFO,
+                },
+            },
+            Shell::{DefSubclassProc, RemoveWindowSubclass, SetWindowSubclass, SUBCLASSPROC},
+            WindowsAndMessaging::*,
+        },
+    },
+};
+
+/// The subset of the Win32 API used by the backend.
+///
+/// `NativePlatform` forwards to the real system calls, `MockPlatform` keeps everything in memory
+/// so the message translation can be driven off-Windows. Platforms are `'static` so the
+/// multi-viewport backend can share them with imgui. The window management methods only it uses
+/// are part of the trait with the `docking` feature alone.
+pub trait Win32Platform: 'static {
+    fn client_rect(&self, hwnd: HWND) -> Result<RECT, Win32ImplError>;
+    fn client_to_screen(&self, hwnd: HWND, pos: POINT) -> Option<POINT>;
+    fn screen_to_client(&self, hwnd: HWND, pos: POINT) -> Option<POINT>;
+    fn cursor_pos(&self) -> Option<POINT>;
+    fn set_cursor_pos(&self, pos: POINT) -> bool;
+    fn key_state(&self, vk: VIRTUAL_KEY) -> i16;
+    fn capture(&self) -> HWND;
+    fn set_capture(&self, hwnd: HWND);
+    fn release_capture(&self);
+    fn load_cursor(&self, id: PCWSTR) -> HCURSOR;
+    fn set_cursor(&self, cursor: HCURSOR);
+    fn track_mouse_event(&self, hwnd: HWND, flags: TRACKMOUSEEVENT_FLAGS) -> bool;
+    fn foreground_window(&self) -> HWND;
+    fn is_child(&self, parent: HWND, hwnd: HWND) -> bool;
+    fn message_time(&self) -> i32;
+    fn message_extra_info(&self) -> LPARAM;
+    fn pointer_type(&self, pointer_id: u32) -> Option<POINTER_INPUT_TYPE>;
+    fn pointer_pen_info(&self, pointer_id: u32) -> Option<POINTER_PEN_INFO>;
+    /// `POINTER_FLAG_*` of the pointer's current message.
+    fn pointer_flags(&self, pointer_id: u32) -> Option<POINTER_FLAGS>;
+    /// DPI of the monitor `hwnd` is on, `USER_DEFAULT_SCREEN_DPI` at 100% scaling.
+    fn dpi_for_window(&self, hwnd: HWND) -> u32;
+    fn peek_message(&self, hwnd: HWND, filter_min: u32, filter_max: u32) -> Option<MSG>;
+    /// Whether `hwnd` is an existing window.
+    fn is_window(&self, hwnd: HWND) -> bool;
+    fn is_window_unicode(&self, hwnd: HWND) -> bool;
+    /// Whether `byte` starts a double-byte character in the keyboard's code page.
+    fn is_dbcs_lead_byte(&self, byte: u8) -> bool;
+    /// Converts text in the keyboard's code page to UTF-16.
+    fn multi_byte_to_wide(&self, bytes: &[u8]) -> Vec<u16>;
+    /// Fails while another window has the clipboard open.
+    fn open_clipboard(&self, owner: HWND) -> bool;
+    fn close_clipboard(&self);
+    /// `CF_UNICODETEXT` contents of the open clipboard, without the terminating nul.
+    fn clipboard_text(&self) -> Option<Vec<u16>>;
+    /// Replaces the contents of the open clipboard with nul-terminated `text`.
+    fn set_clipboard_text(&self, text: &[u16]) -> bool;
+    fn window_from_point(&self, pos: POINT) -> HWND;
+    /// Every monitor of the desktop, the primary one first.
+    fn monitors(&self) -> Vec<MonitorInfo>;
+    /// Registers the window class of secondary viewports, `viewports::CLASS_NAME`.
+    #[cfg(feature = "docking")]
+    fn register_viewport_class(&self) -> bool;
+    #[cfg(feature = "docking")]
+    fn unregister_viewport_class(&self);
+    #[cfg(feature = "docking")]
+    /// Creates a hidden window whose client area covers `rect` once adjusted by
+    /// `adjust_window_rect`, `rect` is in screen coordinates.
+    fn create_window(
+        &self,
+        class_name: PCWSTR,
+        style: WINDOW_STYLE,
+        ex_style: WINDOW_EX_STYLE,
+        rect: RECT,
+        parent: HWND,
+    ) -> HWND;
+    #[cfg(feature = "docking")]
+    fn destroy_window(&self, hwnd: HWND);
+    #[cfg(feature = "docking")]
+    fn show_window(&self, hwnd: HWND, cmd: SHOW_WINDOW_CMD);
+    #[cfg(feature = "docking")]
+    /// Moves and resizes `hwnd` to `rect`, unless `flags` say otherwise.
+    fn set_window_pos(
+        &self,
+        hwnd: HWND,
+        insert_after: HWND,
+        rect: RECT,
+        flags: SET_WINDOW_POS_FLAGS,
+    );
+    #[cfg(feature = "docking")]
+    /// Grows a client rectangle to the window rectangle of a window with these styles.
+    fn adjust_window_rect(
+        &self,
+        rect: RECT,
+        style: WINDOW_STYLE,
+        ex_style: WINDOW_EX_STYLE,
+    ) -> RECT;
+    #[cfg(feature = "docking")]
+    fn set_window_style(&self, hwnd: HWND, style: WINDOW_STYLE, ex_style: WINDOW_EX_STYLE);
+    #[cfg(feature = "docking")]
+    fn set_window_title(&self, hwnd: HWND, title: &str);
+    #[cfg(feature = "docking")]
+    /// Makes the whole window translucent, `1.0` is opaque.
+    fn set_window_alpha(&self, hwnd: HWND, alpha: f32);
+    #[cfg(feature = "docking")]
+    /// Brings `hwnd` to the top and gives it the keyboard focus.
+    fn focus_window(&self, hwnd: HWND);
+    #[cfg(feature = "docking")]
+    fn is_minimized(&self, hwnd: HWND) -> bool;
+    /// Puts `proc` in front of `hwnd`'s window procedure, with `data` passed along to it. Only
+    /// works from the thread owning `hwnd`.
+    fn set_window_subclass(&self, hwnd: HWND, proc: SUBCLASSPROC, id: usize, data: usize) -> bool;
+    fn remove_window_subclass(&self, hwnd: HWND, proc: SUBCLASSPROC, id: usize) -> bool;
+    /// Passes a message on from a subclass to the window procedure behind it.
+    fn def_subclass_proc(&self, hwnd: HWND, msg: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT;
+
+    fn is_key_down(&self, vk: VIRTUAL_KEY) -> bool {
+        (self.key_state(vk) as u16 & 0x8000) != 0
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativePlatform;
+
+impl NativePlatform {
+    // ANSI windows receive characters in the code page of the active input language
+    fn keyboard_code_page() -> u32 {
+        let language = unsafe { GetKeyboardLayout(0) }.0 as u32 & 0xffff;
+        let mut code_page = [0u16; 2];
+        let flags = LOCALE_IDEFAULTANSICODEPAGE | LOCALE_RETURN_NUMBER;
+        if unsafe { GetLocaleInfoW(language, flags, Some(&mut code_page)) } == 0 {
+            CP_ACP
+        } else {
+            code_page[0] as u32 | (code_page[1] as u32) << 16
+        }
+    }
+
+    // GetDpiForWindow (Windows 10 1607) and GetDpiForMonitor (Windows 8.1) are resolved once at
+    // runtime so the crate still loads on systems without them
+    unsafe fn dpi_for_window_or_monitor(hwnd: HWND) -> Option<u32> {
+        type GetDpiForWindowFn = unsafe extern "system" fn(HWND) -> u32;
+        static GET_DPI_FOR_WINDOW: OnceLock<Option<GetDpiForWindowFn>> = OnceLock::new();
+
+        let get_dpi_for_window = GET_DPI_FOR_WINDOW.get_or_init(|| {
+            let user32 = GetModuleHandleW(w!("user32.dll")).ok()?;
+            let proc = GetProcAddress(user32, s!("GetDpiForWindow"))?;
+            let get_dpi_for_window: GetDpiForWindowFn = std::mem::transmute(proc);
+            Some(get_dpi_for_window)
+        });
+        if let Some(get_dpi_for_window) = get_dpi_for_window {
+            match get_dpi_for_window(hwnd) {
+                0 => {}
+                dpi => return Some(dpi),
+            }
+        }
+
+        Self::dpi_for_monitor(MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST))
+    }
+
+    unsafe fn dpi_for_monitor(monitor: HMONITOR) -> Option<u32> {
+        type GetDpiForMonitorFn =
+            unsafe extern "system" fn(HMONITOR, MONITOR_DPI_TYPE, *mut u32, *mut u32) -> i32;
+        static GET_DPI_FOR_MONITOR: OnceLock<Option<GetDpiForMonitorFn>> = OnceLock::new();
+
+        // shcore.dll stays loaded for the rest of the process
+        let get_dpi_for_monitor = (*GET_DPI_FOR_MONITOR.get_or_init(|| {
+            let shcore = LoadLibraryW(w!("shcore.dll")).ok()?;
+            let proc = GetProcAddress(shcore, s!("GetDpiForMonitor"))?;
+            let get_dpi_for_monitor: GetDpiForMonitorFn = std::mem::transmute(proc);
+            Some(get_dpi_for_monitor)
+        }))?;
+        let (mut dpi_x, mut dpi_y) = (0, 0);
+        (get_dpi_for_monitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) >= 0)
+            .then_some(dpi_x)
+    }
+
+    unsafe extern "system" fn push_monitor(
+        monitor: HMONITOR,
+        _hdc: HDC,
+        _rect: *mut RECT,
+        monitors: LPARAM,
+    ) -> BOOL {
+        let monitors = &mut *(monitors.0 as *mut Vec<MonitorInfo>);
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+            return BOOL::from(true);
+        }
+
+        let dpi = Self::dpi_for_monitor(monitor).unwrap_or(USER_DEFAULT_SCREEN_DPI);
+        let monitor = MonitorInfo {
+            rect: info.rcMonitor,
+            work_rect: info.rcWork,
+            dpi_scale: dpi as f32 / USER_DEFAULT_SCREEN_DPI as f32,
+            primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+        };
+        // imgui takes the first monitor for the primary one
+        match monitor.primary {
+            true => monitors.insert(0, monitor),
+            false => monitors.push(monitor),
+        }
+        BOOL::from(true)
+    }
+}
+
+impl Win32Platform for NativePlatform {
+    fn client_rect(&self, hwnd: HWND) -> Result<RECT, Win32ImplError> {
+        let mut rect = RECT::default();
+        if unsafe { GetClientRect(hwnd, &mut rect) }.as_bool() {
+            Ok(rect)
+        } else {
+            Err(Win32ImplError::ExternalError(format!(
+                "GetClientRect failed with last error `{:#?}`",
+                unsafe { GetLastError() }
+            )))
+        }
+    }
+
+    fn client_to_screen(&self, hwnd: HWND, mut pos: POINT) -> Option<POINT> {
+        unsafe { ClientToScreen(hwnd, &mut pos) }
+            .as_bool()
+            .then_some(pos)
+    }
+
+    fn screen_to_client(&self, hwnd: HWND, mut pos: POINT) -> Option<POINT> {
+        unsafe { ScreenToClient(hwnd, &mut pos) }
+            .as_bool()
+            .then_some(pos)
+    }
+
+    fn cursor_pos(&self) -> Option<POINT> {
+        let mut pos = POINT::default();
+        unsafe { GetCursorPos(&mut pos) }.as_bool().then_some(pos)
+    }
+
+    fn set_cursor_pos(&self, pos: POINT) -> bool {
+        unsafe { SetCursorPos(pos.x, pos.y) }.as_bool()
+    }
+
+    fn key_state(&self, vk: VIRTUAL_KEY) -> i16 {
+        unsafe { GetKeyState(vk.0 as i32) }
+    }
+
+    fn capture(&self) -> HWND {
+        unsafe { GetCapture() }
+    }
+
+    fn set_capture(&self, hwnd: HWND) {
+        unsafe { SetCapture(hwnd) };
+    }
+
+    fn release_capture(&self) {
+        unsafe { ReleaseCapture() };
+    }
+
+    fn load_cursor(&self, id: PCWSTR) -> HCURSOR {
+        unsafe { LoadCursorW(None, id) }.unwrap_or_default()
+    }
+
+    fn set_cursor(&self, cursor: HCURSOR) {
+        unsafe { SetCursor(cursor) };
+    }
+
+    fn track_mouse_event(&self, hwnd: HWND, flags: TRACKMOUSEEVENT_FLAGS) -> bool {
+        let mut event = TRACKMOUSEEVENT {
+            cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+            dwFlags: flags,
+            hwndTrack: hwnd,
+            dwHoverTime: 0,
+        };
+        unsafe { TrackMouseEvent(&mut event) }.as_bool()
+    }
+
+    fn foreground_window(&self) -> HWND {
+        unsafe { GetForegroundWindow() }
+    }
+
+    fn is_child(&self, parent: HWND, hwnd: HWND) -> bool {
+        unsafe { IsChild(parent, hwnd) }.as_bool()
+    }
+
+    fn message_time(&self) -> i32 {
+        unsafe { GetMessageTime() }
+    }
+
+    fn message_extra_info(&self) -> LPARAM {
+        unsafe { GetMessageExtraInfo() }
+    }
+
+    fn pointer_type(&self, pointer_id: u32) -> Option<POINTER_INPUT_TYPE> {
+        let mut pointer_type = POINTER_INPUT_TYPE::default();
+        unsafe { GetPointerType(pointer_id, &mut pointer_type) }
+            .as_bool()
+            .then_some(pointer_type)
+    }
+
+    fn pointer_pen_info(&self, pointer_id: u32) -> Option<POINTER_PEN_INFO> {
+        let mut info = POINTER_PEN_INFO::default();
+        unsafe { GetPointerPenInfo(pointer_id, &mut info) }
+            .as_bool()
+            .then_some(info)
+    }
+
+    fn pointer_flags(&self, pointer_id: u32) -> Option<POINTER_FLAGS> {
+        let mut info = POINTER_INFO::default();
+        unsafe { GetPointerInfo(pointer_id, &mut info) }
+            .as_bool()
+            .then_some(info.pointerFlags)
+    }
+
+    fn dpi_for_window(&self, hwnd: HWND) -> u32 {
+        if let Some(dpi) = unsafe { Self::dpi_for_window_or_monitor(hwnd) } {
+            return dpi;
+        }
+
+        // Before Windows 8.1 the DPI is system wide
+        unsafe {
+            let hdc = GetDC(hwnd);
+            let dpi = GetDeviceCaps(hdc, LOGPIXELSX);
+            ReleaseDC(hwnd, hdc);
+            match dpi {
+                dpi if dpi > 0 => dpi as u32,
+                _ => USER_DEFAULT_SCREEN_DPI,
+            }
+        }
+    }
+
+    fn peek_message(&self, hwnd: HWND, filter_min: u32, filter_max: u32) -> Option<MSG> {
+        let mut msg = MSG::default();
+        unsafe { PeekMessageW(&mut msg, hwnd, filter_min, filter_max, PM_NOREMOVE) }
+            .as_bool()
+            .then_some(msg)
+    }
+
+    fn is_window(&self, hwnd: HWND) -> bool {
+        unsafe { IsWindow(hwnd) }.as_bool()
+    }
+
+    fn is_window_unicode(&self, hwnd: HWND) -> bool {
+        unsafe { IsWindowUnicode(hwnd) }.as_bool()
+    }
+
+    fn is_dbcs_lead_byte(&self, byte: u8) -> bool {
+        unsafe { IsDBCSLeadByteEx(Self::keyboard_code_page(), byte) }.as_bool()
+    }
+
+    fn multi_byte_to_wide(&self, bytes: &[u8]) -> Vec<u16> {
+        let code_page = Self::keyboard_code_page();
+        let mut wide = vec![0u16; bytes.len()];
+        let len = unsafe { MultiByteToWideChar(code_page, MB_PRECOMPOSED, bytes, Some(&mut wide)) };
+        wide.truncate(len.max(0) as usize);
+        wide
+    }
+
+    fn open_clipboard(&self, owner: HWND) -> bool {
+        unsafe { OpenClipboard(owner) }.as_bool()
+    }
+
+    fn close_clipboard(&self) {
+        unsafe { CloseClipboard() };
+    }
+
+    fn clipboard_text(&self) -> Option<Vec<u16>> {
+        unsafe {
+            let memory = GetClipboardData(CF_UNICODETEXT.0).ok()?.0;
+            let data = GlobalLock(memory) as *const u16;
+            if data.is_null() {
+                return None;
+            }
+            // Text from other applications isn't always terminated within its allocation
+            let capacity = GlobalSize(memory) / 2;
+            let len = (0..capacity).take_while(|&i| *data.add(i) != 0).count();
+            let text = std::slice::from_raw_parts(data, len).to_vec();
+            GlobalUnlock(memory);
+            Some(text)
+        }
+    }
+
+    fn set_clipboard_text(&self, text: &[u16]) -> bool {
+        unsafe {
+            if !EmptyClipboard().as_bool() {
+                return false;
+            }
+            let memory = GlobalAlloc(GMEM_MOVEABLE, std::mem::size_of_val(text));
+            let data = GlobalLock(memory) as *mut u16;
+            if data.is_null() {
+                GlobalFree(memory);
+                return false;
+            }
+            data.copy_from_nonoverlapping(text.as_ptr(), text.len());
+            GlobalUnlock(memory);
+            // The clipboard owns the memory once it took it
+            if SetClipboardData(CF_UNICODETEXT.0, HANDLE(memory)).is_err() {
+                GlobalFree(memory);
+                return false;
+            }
+            true
+        }
+    }
+
+    fn window_from_point(&self, pos: POINT) -> HWND {
+        unsafe { WindowFromPoint(pos) }
+    }
+
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        let mut monitors = Vec::new();
+        let data = LPARAM(&mut monitors as *mut Vec<MonitorInfo> as isize);
+        unsafe { EnumDisplayMonitors(None, None, Some(Self::push_monitor), data) };
+        monitors
+    }
+
+    #[cfg(feature = "docking")]
+    fn register_viewport_class(&self) -> bool {
+        let class = WNDCLASSEXW {
+            cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+            style: CS_HREDRAW | CS_VREDRAW,
+            lpfnWndProc: Some(crate::viewports::window_proc),
+            hInstance: unsafe { GetModuleHandleW(None) }.unwrap_or_default(),
+            lpszClassName: crate::viewports::CLASS_NAME,
+            ..Default::default()
+        };
+        unsafe { RegisterClassExW(&class) != 0 }
+    }
+
+    #[cfg(feature = "docking")]
+    fn unregister_viewport_class(&self) {
+        let instance = unsafe { GetModuleHandleW(None) }.unwrap_or_default();
+        unsafe { UnregisterClassW(crate::viewports::CLASS_NAME, instance) };
+    }
+
+    #[cfg(feature = "docking")]
+    fn create_window(
+        &self,
+        class_name: PCWSTR,
+        style: WINDOW_STYLE,
+        ex_style: WINDOW_EX_STYLE,
+        rect: RECT,
+        parent: HWND,
+    ) -> HWND {
+        unsafe {
+            CreateWindowExW(
+                ex_style,
+                class_name,
+                w!("Untitled"),
+                style,
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                parent,
+                None,
+                GetModuleHandleW(None).unwrap_or_default(),
+                None,
+            )
+        }
+    }
+
+    #[cfg(feature = "docking")]
+    fn destroy_window(&self, hwnd: HWND) {
+        unsafe { DestroyWindow(hwnd) };
+    }
+
+    #[cfg(feature = "docking")]
+    fn show_window(&self, hwnd: HWND, cmd: SHOW_WINDOW_CMD) {
+        unsafe { ShowWindow(hwnd, cmd) };
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_pos(
+        &self,
+        hwnd: HWND,
+        insert_after: HWND,
+        rect: RECT,
+        flags: SET_WINDOW_POS_FLAGS,
+    ) {
+        let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+        unsafe {
+            SetWindowPos(
+                hwnd,
+                insert_after,
+                rect.left,
+                rect.top,
+                width,
+                height,
+                flags,
+            )
+        };
+    }
+
+    #[cfg(feature = "docking")]
+    fn adjust_window_rect(
+        &self,
+        mut rect: RECT,
+        style: WINDOW_STYLE,
+        ex_style: WINDOW_EX_STYLE,
+    ) -> RECT {
+        unsafe { AdjustWindowRectEx(&mut rect, style, false, ex_style) };
+        rect
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_style(&self, hwnd: HWND, style: WINDOW_STYLE, ex_style: WINDOW_EX_STYLE) {
+        unsafe {
+            SetWindowLongW(hwnd, GWL_STYLE, style.0 as i32);
+            SetWindowLongW(hwnd, GWL_EXSTYLE, ex_style.0 as i32);
+        }
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_title(&self, hwnd: HWND, title: &str) {
+        unsafe { SetWindowTextW(hwnd, &HSTRING::from(title)) };
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_alpha(&self, hwnd: HWND, alpha: f32) {
+        unsafe {
+            let ex_style = WINDOW_EX_STYLE(GetWindowLongW(hwnd, GWL_EXSTYLE) as u32);
+            if alpha < 1.0 {
+                SetWindowLongW(hwnd, GWL_EXSTYLE, (ex_style | WS_EX_LAYERED).0 as i32);
+                let alpha = (alpha.clamp(0.0, 1.0) * 255.0) as u8;
+                SetLayeredWindowAttributes(hwnd, COLORREF(0), alpha, LWA_ALPHA);
+            } else {
+                SetWindowLongW(hwnd, GWL_EXSTYLE, (ex_style & !WS_EX_LAYERED).0 as i32);
+            }
+        }
+    }
+
+    #[cfg(feature = "docking")]
+    fn focus_window(&self, hwnd: HWND) {
+        unsafe {
+            BringWindowToTop(hwnd);
+            SetForegroundWindow(hwnd);
+            SetFocus(hwnd);
+        }
+    }
+
+    #[cfg(feature = "docking")]
+    fn is_minimized(&self, hwnd: HWND) -> bool {
+        unsafe { IsIconic(hwnd) }.as_bool()
+    }
+
+    fn set_window_subclass(&self, hwnd: HWND, proc: SUBCLASSPROC, id: usize, data: usize) -> bool {
+        unsafe { SetWindowSubclass(hwnd, proc, id, data) }.as_bool()
+    }
+
+    fn remove_window_subclass(&self, hwnd: HWND, proc: SUBCLASSPROC, id: usize) -> bool {
+        unsafe { RemoveWindowSubclass(hwnd, proc, id) }.as_bool()
+    }
+
+    fn def_subclass_proc(&self, hwnd: HWND, msg: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        unsafe { DefSubclassProc(hwnd, msg, w_param, l_param) }
+    }
+}
+
+/// In-memory stand-in for the Win32 API.
+///
+/// Screen and client coordinates are related by `client_origin`, system cursors "load" to a handle
+/// equal to their resource id, and every mutating call is recorded so it can be inspected. The
+/// generic `VK_SHIFT`, `VK_CONTROL` and `VK_MENU` report as down when either sided key is down.
+/// Windows are Unicode unless `set_ansi_window` says otherwise, ANSI text is decoded as Latin-1.
+/// Created windows have no decorations, their client area is their whole window rectangle. Any
+/// non-null handle is a window until passed to `destroy_window`.
+#[derive(Debug, Default)]
+pub struct MockPlatform {
+    client_rect: Cell<RECT>,
+    client_origin: Cell<POINT>,
+    cursor_pos: Cell<Option<POINT>>,
+    foreground: Cell<HWND>,
+    capture: Cell<HWND>,
+    cursor: Cell<HCURSOR>,
+    mouse_tracking: RefCell<Vec<(HWND, TRACKMOUSEEVENT_FLAGS)>>,
+    keys_down: RefCell<HashSet<u16>>,
+    message_time: Cell<i32>,
+    message_extra_info: Cell<LPARAM>,
+    pointers: RefCell<HashMap<u32, POINTER_INPUT_TYPE>>,
+    pen_info: RefCell<HashMap<u32, POINTER_PEN_INFO>>,
+    pointer_flags: RefCell<HashMap<u32, POINTER_FLAGS>>,
+    dpi: Cell<Option<u32>>,
+    queue: RefCell<VecDeque<MSG>>,
+    ansi_window: Cell<bool>,
+    clipboard: RefCell<Option<Vec<u16>>>,
+    clipboard_owner: Cell<Option<HWND>>,
+    last_clipboard_owner: Cell<Option<HWND>>,
+    clipboard_busy: Cell<u32>,
+    monitors: RefCell<Vec<MonitorInfo>>,
+    viewport_class: Cell<bool>,
+    windows: RefCell<BTreeMap<isize, MockWindow>>,
+    #[cfg(feature = "docking")]
+    created_windows: Cell<isize>,
+    window_creation_fails: Cell<bool>,
+    destroyed_windows: RefCell<HashSet<isize>>,
+    subclasses: RefCell<Vec<MockSubclass>>,
+    window_proc_messages: RefCell<Vec<(HWND, u32)>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MockSubclass {
+    hwnd: HWND,
+    proc: SUBCLASSPROC,
+    id: usize,
+    data: usize,
+}
+
+// Handles of windows made by `MockPlatform::create_window` count up from here
+#[cfg(feature = "docking")]
+const FIRST_MOCK_WINDOW: isize = 0x2000;
+
+/// A window made by `MockPlatform::create_window`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockWindow {
+    pub style: WINDOW_STYLE,
+    pub ex_style: WINDOW_EX_STYLE,
+    pub parent: HWND,
+    /// Client area in screen coordinates.
+    pub rect: RECT,
+    pub title: String,
+    pub visible: bool,
+    pub alpha: f32,
+    pub minimized: bool,
+}
+
+impl MockPlatform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_client_rect(&self, rect: RECT) {
+        self.client_rect.set(rect);
+    }
+
+    pub fn set_client_origin(&self, origin: POINT) {
+        self.client_origin.set(origin);
+    }
+
+    /// Moves the cursor, in screen coordinates. `None` makes `GetCursorPos` fail.
+    pub fn move_cursor(&self, pos: Option<POINT>) {
+        self.cursor_pos.set(pos);
+    }
+
+    pub fn set_foreground_window(&self, hwnd: HWND) {
+        self.foreground.set(hwnd);
+    }
+
+    pub fn set_key_down(&self, vk: VIRTUAL_KEY, down: bool) {
+        let mut keys = self.keys_down.borrow_mut();
+        if down {
+            keys.insert(vk.0);
+        } else {
+            keys.remove(&vk.0);
+        }
+    }
+
+    pub fn set_message_time(&self, time: i32) {
+        self.message_time.set(time);
+    }
+
+    pub fn set_message_extra_info(&self, extra_info: LPARAM) {
+        self.message_extra_info.set(extra_info);
+    }
+
+    pub fn set_pointer_type(&self, pointer_id: u32, pointer_type: POINTER_INPUT_TYPE) {
+        self.pointers.borrow_mut().insert(pointer_id, pointer_type);
+    }
+
+    pub fn set_pointer_flags(&self, pointer_id: u32, flags: POINTER_FLAGS) {
+        self.pointer_flags.borrow_mut().insert(pointer_id, flags);
+    }
+
+    pub fn set_pointer_pen_info(&self, pointer_id: u32, info: POINTER_PEN_INFO) {
+        self.pen_info.borrow_mut().insert(pointer_id, info);
+    }
+
+    /// Sets the DPI of every window, `USER_DEFAULT_SCREEN_DPI` until set.
+    pub fn set_dpi(&self, dpi: u32) {
+        self.dpi.set(Some(dpi));
+    }
+
+    /// Appends a message to the queue seen by `peek_message`.
+    pub fn post_message(&self, msg: MSG) {
+        self.queue.borrow_mut().push_back(msg);
+    }
+
+    pub fn clear_messages(&self) {
+        self.queue.borrow_mut().clear();
+    }
+
+    pub fn set_ansi_window(&self, ansi: bool) {
+        self.ansi_window.set(ansi);
+    }
+
+    /// Puts `text` on the clipboard, as if copied by another application.
+    pub fn put_clipboard(&self, text: &str) {
+        *self.clipboard.borrow_mut() = Some(text.encode_utf16().collect());
+    }
+
+    pub fn clipboard_contents(&self) -> Option<String> {
+        self.clipboard
+            .borrow()
+            .as_ref()
+            .map(|text| String::from_utf16_lossy(text))
+    }
+
+    /// Makes the next `attempts` calls to `open_clipboard` fail, as if another window had it open.
+    pub fn set_clipboard_busy(&self, attempts: u32) {
+        self.clipboard_busy.set(attempts);
+    }
+
+    /// The window the clipboard is open for, `None` once closed.
+    pub fn clipboard_owner(&self) -> Option<HWND> {
+        self.clipboard_owner.get()
+    }
+
+    /// The window passed to the latest `OpenClipboard` call, whether it succeeded or not.
+    pub fn last_clipboard_owner(&self) -> Option<HWND> {
+        self.last_clipboard_owner.get()
+    }
+
+    pub fn set_monitors(&self, monitors: Vec<MonitorInfo>) {
+        *self.monitors.borrow_mut() = monitors;
+    }
+
+    /// Destroys `hwnd` behind the backend's back, like an application closing its window.
+    pub fn remove_window(&self, hwnd: HWND) {
+        self.windows.borrow_mut().remove(&hwnd.0);
+        self.destroyed_windows.borrow_mut().insert(hwnd.0);
+    }
+
+    pub fn set_window_minimized(&self, hwnd: HWND, minimized: bool) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(&hwnd.0) {
+            window.minimized = minimized;
+        }
+    }
+
+    pub fn viewport_class_registered(&self) -> bool {
+        self.viewport_class.get()
+    }
+
+    /// Makes `create_window` return a null handle, like a failed `CreateWindowEx`.
+    pub fn set_window_creation_fails(&self, fails: bool) {
+        self.window_creation_fails.set(fails);
+    }
+
+    pub fn window(&self, hwnd: HWND) -> Option<MockWindow> {
+        self.windows.borrow().get(&hwnd.0).cloned()
+    }
+
+    /// Windows created and not destroyed yet, oldest first.
+    pub fn windows(&self) -> Vec<(HWND, MockWindow)> {
+        self.windows
+            .borrow()
+            .iter()
+            .map(|(&hwnd, window)| (HWND(hwnd), window.clone()))
+            .collect()
+    }
+
+    fn client_origin(&self, hwnd: HWND) -> POINT {
+        match self.windows.borrow().get(&hwnd.0) {
+            Some(window) => POINT {
+                x: window.rect.left,
+                y: window.rect.top,
+            },
+            None => self.client_origin.get(),
+        }
+    }
+
+    #[cfg(feature = "docking")]
+    fn update_window(&self, hwnd: HWND, update: impl FnOnce(&mut MockWindow)) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(&hwnd.0) {
+            update(window);
+        }
+    }
+
+    pub fn captured_window(&self) -> HWND {
+        self.capture.get()
+    }
+
+    pub fn current_cursor(&self) -> HCURSOR {
+        self.cursor.get()
+    }
+
+    pub fn cursor_screen_pos(&self) -> Option<POINT> {
+        self.cursor_pos.get()
+    }
+
+    /// Every `TrackMouseEvent` call made so far, oldest first.
+    pub fn mouse_tracking_requests(&self) -> Vec<(HWND, TRACKMOUSEEVENT_FLAGS)> {
+        self.mouse_tracking.borrow().clone()
+    }
+
+    pub fn is_subclassed(&self, hwnd: HWND) -> bool {
+        self.subclasses.borrow().iter().any(|s| s.hwnd == hwnd)
+    }
+
+    /// Delivers a message to `hwnd` like `DispatchMessage`, through the latest subclass if any.
+    /// Subclasses pass messages on straight to the window's own procedure, which records them and
+    /// returns `0`.
+    ///
+    /// # Safety
+    ///
+    /// Calls the installed subclass procedure with the data it was installed with.
+    pub unsafe fn call_window_proc(
+        &self,
+        hwnd: HWND,
+        msg: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> LRESULT {
+        let subclass = self
+            .subclasses
+            .borrow()
+            .iter()
+            .rev()
+            .find(|s| s.hwnd == hwnd)
+            .copied();
+        match subclass.and_then(|s| Some((s.proc?, s))) {
+            Some((proc, s)) => proc(hwnd, msg, w_param, l_param, s.id, s.data),
+            None => self.def_subclass_proc(hwnd, msg, w_param, l_param),
+        }
+    }
+
+    /// Messages that reached the window procedure of `hwnd` behind its subclasses, oldest first.
+    pub fn window_proc_messages(&self, hwnd: HWND) -> Vec<u32> {
+        self.window_proc_messages
+            .borrow()
+            .iter()
+            .filter(|&&(window, _)| window == hwnd)
+            .map(|&(_, msg)| msg)
+            .collect()
+    }
+}
+
+impl Win32Platform for MockPlatform {
+    fn client_rect(&self, hwnd: HWND) -> Result<RECT, Win32ImplError> {
+        match self.windows.borrow().get(&hwnd.0) {
+            Some(window) => Ok(RECT {
+                left: 0,
+                top: 0,
+                right: window.rect.right - window.rect.left,
+                bottom: window.rect.bottom - window.rect.top,
+            }),
+            None => Ok(self.client_rect.get()),
+        }
+    }
+
+    fn client_to_screen(&self, hwnd: HWND, pos: POINT) -> Option<POINT> {
+        let origin = self.client_origin(hwnd);
+        Some(POINT {
+            x: pos.x + origin.x,
+            y: pos.y + origin.y,
+        })
+    }
+
+    fn screen_to_client(&self, hwnd: HWND, pos: POINT) -> Option<POINT> {
+        let origin = self.client_origin(hwnd);
+        Some(POINT {
+            x: pos.x - origin.x,
+            y: pos.y - origin.y,
+        })
+    }
+
+    fn cursor_pos(&self) -> Option<POINT> {
+        self.cursor_pos.get()
+    }
+
+    fn set_cursor_pos(&self, pos: POINT) -> bool {
+        self.cursor_pos.set(Some(pos));
+        true
+    }
+
+    fn key_state(&self, vk: VIRTUAL_KEY) -> i16 {
+        let keys = self.keys_down.borrow();
+        let sided = match vk {
+            VK_SHIFT => [VK_LSHIFT, VK_RSHIFT],
+            VK_CONTROL => [VK_LCONTROL, VK_RCONTROL],
+            VK_MENU => [VK_LMENU, VK_RMENU],
+            _ => [vk, vk],
+        };
+        if keys.contains(&vk.0) || sided.iter().any(|vk| keys.contains(&vk.0)) {
+            0x8000u16 as i16
+        } else {
+            0
+        }
+    }
+
+    fn capture(&self) -> HWND {
+        self.capture.get()
+    }
+
+    fn set_capture(&self, hwnd: HWND) {
+        self.capture.set(hwnd);
+    }
+
+    // Like ReleaseCapture, the window losing the capture hears about it before this returns
+    fn release_capture(&self) {
+        let hwnd = self.capture.replace(HWND(0));
+        if hwnd.0 != 0 {
+            unsafe { self.call_window_proc(hwnd, WM_CAPTURECHANGED, WPARAM(0), LPARAM(0)) };
+        }
+    }
+
+    fn load_cursor(&self, id: PCWSTR) -> HCURSOR {
+        HCURSOR(id.0 as isize)
+    }
+
+    fn set_cursor(&self, cursor: HCURSOR) {
+        self.cursor.set(cursor);
+    }
+
+    fn track_mouse_event(&self, hwnd: HWND, flags: TRACKMOUSEEVENT_FLAGS) -> bool {
+        self.mouse_tracking.borrow_mut().push((hwnd, flags));
+        true
+    }
+
+    fn foreground_window(&self) -> HWND {
+        self.foreground.get()
+    }
+
+    fn is_child(&self, _parent: HWND, _hwnd: HWND) -> bool {
+        false
+    }
+
+    fn message_time(&self) -> i32 {
+        self.message_time.get()
+    }
+
+    fn message_extra_info(&self) -> LPARAM {
+        self.message_extra_info.get()
+    }
+
+    fn pointer_type(&self, pointer_id: u32) -> Option<POINTER_INPUT_TYPE> {
+        self.pointers.borrow().get(&pointer_id).copied()
+    }
+
+    fn pointer_pen_info(&self, pointer_id: u32) -> Option<POINTER_PEN_INFO> {
+        self.pen_info.borrow().get(&pointer_id).copied()
+    }
+
+    fn pointer_flags(&self, pointer_id: u32) -> Option<POINTER_FLAGS> {
+        self.pointer_flags.borrow().get(&pointer_id).copied()
+    }
+
+    fn dpi_for_window(&self, _hwnd: HWND) -> u32 {
+        self.dpi.get().unwrap_or(USER_DEFAULT_SCREEN_DPI)
+    }
+
+    fn peek_message(&self, hwnd: HWND, filter_min: u32, filter_max: u32) -> Option<MSG> {
+        self.queue
+            .borrow()
+            .iter()
+            .find(|msg| {
+                (hwnd.0 == 0 || msg.hwnd == hwnd)
+                    && (filter_min == 0 && filter_max == 0
+                        || (filter_min..=filter_max).contains(&msg.message))
+            })
+            .copied()
+    }
+
+    fn is_window(&self, hwnd: HWND) -> bool {
+        hwnd.0 != 0 && !self.destroyed_windows.borrow().contains(&hwnd.0)
+    }
+
+    fn is_window_unicode(&self, _hwnd: HWND) -> bool {
+        !self.ansi_window.get()
+    }
+
+    fn is_dbcs_lead_byte(&self, _byte: u8) -> bool {
+        false
+    }
+
+    fn multi_byte_to_wide(&self, bytes: &[u8]) -> Vec<u16> {
+        bytes.iter().map(|&b| b as u16).collect()
+    }
+
+    fn open_clipboard(&self, owner: HWND) -> bool {
+        self.last_clipboard_owner.set(Some(owner));
+        match self.clipboard_busy.get() {
+            0 if self.clipboard_owner.get().is_none() => {
+                self.clipboard_owner.set(Some(owner));
+                true
+            }
+            0 => false,
+            busy => {
+                self.clipboard_busy.set(busy - 1);
+                false
+            }
+        }
+    }
+
+    fn close_clipboard(&self) {
+        self.clipboard_owner.set(None);
+    }
+
+    fn clipboard_text(&self) -> Option<Vec<u16>> {
+        self.clipboard_owner.get()?;
+        self.clipboard.borrow().clone()
+    }
+
+    fn set_clipboard_text(&self, text: &[u16]) -> bool {
+        if self.clipboard_owner.get().is_none() {
+            return false;
+        }
+        let text = text.split(|&c| c == 0).next().unwrap_or_default();
+        *self.clipboard.borrow_mut() = Some(text.to_vec());
+        true
+    }
+
+    fn window_from_point(&self, pos: POINT) -> HWND {
+        let windows = self.windows.borrow();
+        let contains = |rect: &RECT| {
+            (rect.left..rect.right).contains(&pos.x) && (rect.top..rect.bottom).contains(&pos.y)
+        };
+        windows
+            .iter()
+            .rev()
+            .find(|(_, window)| window.visible && contains(&window.rect))
+            .map(|(&hwnd, _)| HWND(hwnd))
+            .unwrap_or_default()
+    }
+
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        self.monitors.borrow().clone()
+    }
+
+    #[cfg(feature = "docking")]
+    fn register_viewport_class(&self) -> bool {
+        self.viewport_class.set(true);
+        true
+    }
+
+    #[cfg(feature = "docking")]
+    fn unregister_viewport_class(&self) {
+        self.viewport_class.set(false);
+    }
+
+    #[cfg(feature = "docking")]
+    fn create_window(
+        &self,
+        _class_name: PCWSTR,
+        style: WINDOW_STYLE,
+        ex_style: WINDOW_EX_STYLE,
+        rect: RECT,
+        parent: HWND,
+    ) -> HWND {
+        if self.window_creation_fails.get() {
+            return HWND(0);
+        }
+        let hwnd = FIRST_MOCK_WINDOW + self.created_windows.get();
+        self.created_windows.set(self.created_windows.get() + 1);
+        let window = MockWindow {
+            style,
+            ex_style,
+            parent,
+            rect,
+            title: String::from("Untitled"),
+            visible: false,
+            alpha: 1.0,
+            minimized: false,
+        };
+        self.windows.borrow_mut().insert(hwnd, window);
+        HWND(hwnd)
+    }
+
+    #[cfg(feature = "docking")]
+    fn destroy_window(&self, hwnd: HWND) {
+        self.remove_window(hwnd);
+    }
+
+    #[cfg(feature = "docking")]
+    fn show_window(&self, hwnd: HWND, cmd: SHOW_WINDOW_CMD) {
+        self.update_window(hwnd, |window| window.visible = cmd != SW_HIDE);
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_pos(
+        &self,
+        hwnd: HWND,
+        _insert_after: HWND,
+        rect: RECT,
+        flags: SET_WINDOW_POS_FLAGS,
+    ) {
+        self.update_window(hwnd, |window| {
+            let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+            let (left, top) = match flags & SWP_NOMOVE {
+                SWP_NOMOVE => (window.rect.left, window.rect.top),
+                _ => (rect.left, rect.top),
+            };
+            let (width, height) = match flags & SWP_NOSIZE {
+                SWP_NOSIZE => (
+                    window.rect.right - window.rect.left,
+                    window.rect.bottom - window.rect.top,
+                ),
+                _ => (width, height),
+            };
+            window.rect = RECT {
+                left,
+                top,
+                right: left + width,
+                bottom: top + height,
+            };
+        });
+    }
+
+    #[cfg(feature = "docking")]
+    fn adjust_window_rect(
+        &self,
+        rect: RECT,
+        _style: WINDOW_STYLE,
+        _ex_style: WINDOW_EX_STYLE,
+    ) -> RECT {
+        rect
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_style(&self, hwnd: HWND, style: WINDOW_STYLE, ex_style: WINDOW_EX_STYLE) {
+        self.update_window(hwnd, |window| {
+            window.style = style;
+            window.ex_style = ex_style;
+        });
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_title(&self, hwnd: HWND, title: &str) {
+        self.update_window(hwnd, |window| window.title = title.to_owned());
+    }
+
+    #[cfg(feature = "docking")]
+    fn set_window_alpha(&self, hwnd: HWND, alpha: f32) {
+        self.update_window(hwnd, |window| window.alpha = alpha);
+    }
+
+    #[cfg(feature = "docking")]
+    fn focus_window(&self, hwnd: HWND) {
+        self.foreground.set(hwnd);
+    }
+
+    #[cfg(feature = "docking")]
+    fn is_minimized(&self, hwnd: HWND) -> bool {
+        self.window(hwnd).is_some_and(|window| window.minimized)
+    }
+
+    fn set_window_subclass(&self, hwnd: HWND, proc: SUBCLASSPROC, id: usize, data: usize) -> bool {
+        if !self.is_window(hwnd) {
+            return false;
+        }
+        let mut subclasses = self.subclasses.borrow_mut();
+        // Subclasses are told apart by id alone, installing one again only replaces its data
+        match subclasses.iter_mut().find(|s| s.hwnd == hwnd && s.id == id) {
+            Some(subclass) => subclass.data = data,
+            None => subclasses.push(MockSubclass {
+                hwnd,
+                proc,
+                id,
+                data,
+            }),
+        }
+        true
+    }
+
+    fn remove_window_subclass(&self, hwnd: HWND, _proc: SUBCLASSPROC, id: usize) -> bool {
+        let mut subclasses = self.subclasses.borrow_mut();
+        let count = subclasses.len();
+        subclasses.retain(|s| !(s.hwnd == hwnd && s.id == id));
+        subclasses.len() != count
+    }
+
+    fn def_subclass_proc(
+        &self,
+        hwnd: HWND,
+        msg: u32,
+        _w_param: WPARAM,
+        _l_param: LPARAM,
+    ) -> LRESULT {
+        self.window_proc_messages.borrow_mut().push((hwnd, msg));
+        LRESULT(0)
+    }
+}
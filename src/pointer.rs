@@ -0,0 +1,459 @@
+use crate::{
+    get_x_lparam, get_y_lparam, hiword, loword, state::WindowState, viewports_enabled,
+    wheel_notches, MouseSource, ProcResponse, Win32Platform,
+};
+use imgui::{Io, MouseButton};
+use windows::Win32::{
+    Foundation::{HWND, LRESULT, POINT},
+    UI::Input::Pointer::POINTER_FLAG_PRIMARY,
+    UI::WindowsAndMessaging::{
+        PEN_FLAG_BARREL, PEN_FLAG_ERASER, PEN_FLAG_INVERTED, POINTER_MESSAGE_FLAG_INCONTACT,
+        POINTER_MESSAGE_FLAG_INRANGE, POINTER_MESSAGE_FLAG_PRIMARY, PT_PEN, PT_TOUCH,
+        WM_POINTERDOWN, WM_POINTERHWHEEL, WM_POINTERLEAVE, WM_POINTERUP, WM_POINTERUPDATE,
+        WM_POINTERWHEEL,
+    },
+};
+
+// `POINTER_PEN_INFO::pressure` is normalized to this range
+const MAX_PEN_PRESSURE: f32 = 1024.0;
+
+/// A touch contact or pen seen through `WM_POINTER*` messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointerContact {
+    pub id: u32,
+    /// `MouseSource::TouchScreen` or `MouseSource::Pen`.
+    pub source: MouseSource,
+    /// Position in client coordinates, in screen coordinates when multi-viewports are enabled.
+    pub pos: [f32; 2],
+    /// The primary pointer is the one driving imgui's mouse.
+    pub primary: bool,
+    /// Touching the screen, as opposed to a pen hovering in range.
+    pub in_contact: bool,
+    /// Pen pressure from `0.0` to `1.0`, `None` for touch.
+    pub pressure: Option<f32>,
+    /// The pen's barrel button is pressed.
+    pub barrel: bool,
+    /// The pen is inverted or its eraser button is pressed.
+    pub eraser: bool,
+    /// The contact was lifted during the frame, it won't show up in the next one.
+    pub released: bool,
+}
+
+/// Every pointer contact seen during a frame.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PointerFrame {
+    pub contacts: Vec<PointerContact>,
+}
+
+impl PointerFrame {
+    pub fn primary(&self) -> Option<&PointerContact> {
+        self.contacts.iter().find(|contact| contact.primary)
+    }
+
+    pub fn pen(&self) -> Option<&PointerContact> {
+        self.contacts
+            .iter()
+            .find(|contact| contact.source == MouseSource::Pen)
+    }
+
+    /// Snapshot for the frame about to start, contacts lifted during the last one are dropped
+    /// afterwards.
+    pub(crate) fn end_frame(&mut self) -> PointerFrame {
+        let frame = self.clone();
+        self.contacts.retain(|contact| !contact.released);
+        frame
+    }
+
+    fn contact_mut(&mut self, id: u32, source: MouseSource) -> &mut PointerContact {
+        match self.contacts.iter().position(|contact| contact.id == id) {
+            Some(index) => &mut self.contacts[index],
+            None => {
+                self.contacts.push(PointerContact {
+                    id,
+                    source,
+                    pos: [0.0, 0.0],
+                    primary: false,
+                    in_contact: false,
+                    pressure: None,
+                    barrel: false,
+                    eraser: false,
+                    released: false,
+                });
+                self.contacts.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Handles `WM_POINTERDOWN`, `WM_POINTERUPDATE`, `WM_POINTERUP`, `WM_POINTERLEAVE`,
+/// `WM_POINTERWHEEL` and `WM_POINTERHWHEEL` from touch and pen pointers.
+///
+/// The primary pointer drives imgui's mouse, with the pen's barrel button mapped to the right
+/// button. A pointer that leaves the window or goes out of range is dropped after the frame and
+/// moves imgui's mouse off-screen if it was the primary one. Handled messages answer `Handled(0)` so the host skips `DefWindowProc` and Windows
+/// doesn't synthesize mouse messages for them as well. Mouse and touchpad pointers are left to
+/// the regular mouse messages.
+pub(crate) fn handle_pointer<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    state: &mut WindowState,
+    io: &mut Io,
+    msg: u32,
+    w_param: u32,
+    l_param: u32,
+) -> ProcResponse {
+    let id = loword(w_param) as u32;
+    let source = match platform.pointer_type(id) {
+        Some(PT_TOUCH) => MouseSource::TouchScreen,
+        Some(PT_PEN) => MouseSource::Pen,
+        _ => return ProcResponse::PassThrough,
+    };
+
+    if msg == WM_POINTERWHEEL || msg == WM_POINTERHWHEEL {
+        // The high word carries the wheel delta here instead of the pointer flags
+        let primary = platform
+            .pointer_flags(id)
+            .is_some_and(|flags| flags.0 & POINTER_FLAG_PRIMARY.0 != 0);
+        if primary {
+            let notches = wheel_notches(w_param);
+            match msg {
+                WM_POINTERWHEEL => io.add_mouse_wheel_event([0.0, notches]),
+                _ => io.add_mouse_wheel_event([-notches, 0.0]),
+            }
+        }
+        return ProcResponse::Handled(LRESULT(0));
+    }
+
+    let flags = hiword(w_param) as u32;
+    let primary = flags & POINTER_MESSAGE_FLAG_PRIMARY != 0;
+
+    // A lifted finger is out of range as well, but `WM_POINTERUP` still carries its release
+    if msg == WM_POINTERLEAVE
+        || (msg == WM_POINTERUPDATE && flags & POINTER_MESSAGE_FLAG_INRANGE == 0)
+    {
+        leave(state, io, id);
+        return ProcResponse::Handled(LRESULT(0));
+    }
+
+    // Pointer messages are in screen coordinates
+    let screen_pos = POINT {
+        x: get_x_lparam(l_param),
+        y: get_y_lparam(l_param),
+    };
+    let pos = match viewports_enabled(io) {
+        true => Some(screen_pos),
+        false => platform.screen_to_client(window, screen_pos),
+    };
+    let pos = match pos {
+        Some(pos) => [pos.x as f32, pos.y as f32],
+        None => return ProcResponse::PassThrough,
+    };
+
+    let pen = match source {
+        MouseSource::Pen => platform.pointer_pen_info(id),
+        _ => None,
+    };
+
+    let contact = state.pointers.contact_mut(id, source);
+    contact.pos = pos;
+    contact.primary = primary;
+    contact.in_contact = flags & POINTER_MESSAGE_FLAG_INCONTACT != 0;
+    contact.pressure = pen.map(|pen| pen.pressure as f32 / MAX_PEN_PRESSURE);
+    contact.barrel = pen.is_some_and(|pen| pen.penFlags & PEN_FLAG_BARREL != 0);
+    contact.eraser =
+        pen.is_some_and(|pen| pen.penFlags & (PEN_FLAG_ERASER | PEN_FLAG_INVERTED) != 0);
+    contact.released = msg == WM_POINTERUP;
+    let barrel = contact.barrel;
+
+    if primary {
+        state.mouse_source = source;
+        io.add_mouse_pos_event(pos);
+        match msg {
+            WM_POINTERDOWN => {
+                let button = match barrel {
+                    true => MouseButton::Right,
+                    false => MouseButton::Left,
+                };
+                state.pointer_button = Some(button);
+                io.add_mouse_button_event(button, true);
+            }
+            WM_POINTERUP => {
+                if let Some(button) = state.pointer_button.take() {
+                    io.add_mouse_button_event(button, false);
+                }
+                // A lifted finger doesn't hover, don't leave widgets highlighted under it
+                if source == MouseSource::TouchScreen {
+                    io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ProcResponse::Handled(LRESULT(0))
+}
+
+// The pointer is gone without a `WM_POINTERUP` when a hovering pen leaves range
+fn leave(state: &mut WindowState, io: &mut Io, id: u32) {
+    let contact = match state
+        .pointers
+        .contacts
+        .iter_mut()
+        .find(|contact| contact.id == id)
+    {
+        Some(contact) => contact,
+        None => return,
+    };
+    contact.in_contact = false;
+    contact.released = true;
+
+    if contact.primary {
+        if let Some(button) = state.pointer_button.take() {
+            io.add_mouse_button_event(button, false);
+        }
+        io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use imgui::MouseButton;
+    use windows::Win32::{
+        Foundation::LRESULT,
+        UI::{
+            Input::Pointer::{POINTER_FLAG_INCONTACT, POINTER_PEN_INFO},
+            WindowsAndMessaging::*,
+        },
+    };
+
+    fn contact(id: u32, released: bool) -> PointerContact {
+        PointerContact {
+            id,
+            source: MouseSource::TouchScreen,
+            pos: [0.0, 0.0],
+            primary: id == 1,
+            in_contact: !released,
+            pressure: None,
+            barrel: false,
+            eraser: false,
+            released,
+        }
+    }
+
+    #[test]
+    fn released_contacts_last_one_frame() {
+        let mut pointers = PointerFrame {
+            contacts: vec![contact(1, false), contact(2, true)],
+        };
+
+        let frame = pointers.end_frame();
+        assert_eq!(frame.contacts.len(), 2);
+        assert_eq!(frame.primary().map(|contact| contact.id), Some(1));
+
+        let frame = pointers.end_frame();
+        assert_eq!(frame.contacts, [contact(1, false)]);
+    }
+
+    fn pointer_wparam(id: u16, flags: u32) -> usize {
+        (flags << 16 | id as u32) as usize
+    }
+
+    #[test]
+    fn pointer_messages_are_left_alone_unless_enabled() {
+        let (_guard, _ctx, mut backend) = setup();
+        backend.platform().set_pointer_type(1, PT_TOUCH);
+        let w_param = pointer_wparam(1, POINTER_MESSAGE_FLAG_PRIMARY);
+
+        assert_eq!(
+            send(backend.platform(), WM_POINTERDOWN, w_param, 0),
+            ProcResponse::PassThrough
+        );
+
+        // Handled pointer messages answer 0, or Windows makes mouse messages out of them
+        backend.set_pointer_input(true);
+        for msg in [WM_POINTERDOWN, WM_POINTERUPDATE, WM_POINTERUP] {
+            assert_eq!(
+                send(backend.platform(), msg, w_param, 0),
+                ProcResponse::Handled(LRESULT(0))
+            );
+        }
+
+        backend.platform().set_pointer_type(2, PT_MOUSE);
+        assert_eq!(
+            send(backend.platform(), WM_POINTERDOWN, pointer_wparam(2, 0), 0),
+            ProcResponse::PassThrough
+        );
+    }
+
+    #[test]
+    fn pointer_wheel_scrolls_for_the_primary_pointer() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_pointer_input(true);
+        let platform = backend.platform();
+        platform.set_pointer_type(1, PT_PEN);
+        platform.set_pointer_flags(1, POINTER_FLAG_PRIMARY);
+        platform.set_pointer_type(2, PT_PEN);
+        platform.set_pointer_flags(2, POINTER_FLAG_INCONTACT);
+
+        // The high word is the delta, whose bits mustn't be taken for flags
+        for (delta, notches) in [(120, 1.0), (-120, -1.0)] {
+            for id in [1, 2] {
+                let w_param = wheel_wparam(delta) | id;
+                assert_eq!(
+                    send(platform, WM_POINTERWHEEL, w_param, 0),
+                    ProcResponse::Handled(LRESULT(0))
+                );
+                send(platform, WM_POINTERHWHEEL, w_param, 0);
+            }
+
+            let ui = ctx.new_frame();
+            assert_eq!(ui.io().mouse_wheel, notches);
+            assert_eq!(ui.io().mouse_wheel_h, -notches);
+            ctx.render();
+        }
+    }
+
+    #[test]
+    fn primary_touch_drives_the_mouse() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_pointer_input(true);
+        backend.platform().set_pointer_type(1, PT_TOUCH);
+        backend.platform().set_pointer_type(2, PT_TOUCH);
+        let primary = POINTER_MESSAGE_FLAG_PRIMARY | POINTER_MESSAGE_FLAG_INCONTACT;
+
+        send(
+            backend.platform(),
+            WM_POINTERDOWN,
+            pointer_wparam(1, primary),
+            mouse_lparam(130, 70),
+        );
+        send(
+            backend.platform(),
+            WM_POINTERDOWN,
+            pointer_wparam(2, POINTER_MESSAGE_FLAG_INCONTACT),
+            mouse_lparam(300, 250),
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let pointers = backend.pointers();
+        assert_eq!(pointers.contacts.len(), 2);
+        assert_eq!(pointers.primary().unwrap().pos, [30.0, 20.0]);
+        assert_eq!(pointers.contacts[1].pos, [200.0, 200.0]);
+        assert_eq!(backend.mouse_source(), MouseSource::TouchScreen);
+
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [30.0, 20.0]);
+        assert!(ui.is_mouse_down(MouseButton::Left));
+        ctx.render();
+
+        send(
+            backend.platform(),
+            WM_POINTERUP,
+            pointer_wparam(1, POINTER_MESSAGE_FLAG_PRIMARY),
+            mouse_lparam(130, 70),
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(backend.pointers().primary().unwrap().released);
+
+        let ui = ctx.new_frame();
+        assert!(!ui.is_mouse_down(MouseButton::Left));
+        ctx.render();
+
+        // The lifted finger stops hovering once the release went through
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert_eq!(backend.pointers().contacts.len(), 1);
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [-f32::MAX, -f32::MAX]);
+    }
+
+    #[test]
+    fn pen_reports_pressure_and_barrel_button() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_pointer_input(true);
+        backend.platform().set_pointer_type(7, PT_PEN);
+        backend.platform().set_pointer_pen_info(
+            7,
+            POINTER_PEN_INFO {
+                penFlags: PEN_FLAG_BARREL,
+                pressure: 512,
+                ..Default::default()
+            },
+        );
+
+        send(
+            backend.platform(),
+            WM_POINTERDOWN,
+            pointer_wparam(
+                7,
+                POINTER_MESSAGE_FLAG_PRIMARY | POINTER_MESSAGE_FLAG_INCONTACT,
+            ),
+            mouse_lparam(110, 60),
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+
+        let pen = backend.pointers().pen().unwrap();
+        assert_eq!(pen.pressure, Some(0.5));
+        assert!(pen.barrel);
+        assert!(!pen.eraser);
+        assert_eq!(backend.mouse_source(), MouseSource::Pen);
+
+        let ui = ctx.new_frame();
+        assert!(ui.is_mouse_down(MouseButton::Right));
+    }
+
+    #[test]
+    fn pen_leaving_range_stops_hovering() {
+        let (_guard, mut ctx, mut backend) = setup();
+        backend.set_pointer_input(true);
+        backend.platform().set_pointer_type(7, PT_PEN);
+        let hovering = POINTER_MESSAGE_FLAG_PRIMARY | POINTER_MESSAGE_FLAG_INRANGE;
+
+        send(
+            backend.platform(),
+            WM_POINTERUPDATE,
+            pointer_wparam(7, hovering),
+            mouse_lparam(110, 60),
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(!backend.pointers().pen().unwrap().in_contact);
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [10.0, 10.0]);
+        ctx.render();
+
+        assert_eq!(
+            send(
+                backend.platform(),
+                WM_POINTERLEAVE,
+                pointer_wparam(7, POINTER_MESSAGE_FLAG_PRIMARY),
+                mouse_lparam(110, 60),
+            ),
+            ProcResponse::Handled(LRESULT(0))
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(backend.pointers().pen().unwrap().released);
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [-f32::MAX, -f32::MAX]);
+        ctx.render();
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(backend.pointers().contacts.is_empty());
+
+        // Some drivers only clear the in-range flag
+        send(
+            backend.platform(),
+            WM_POINTERUPDATE,
+            pointer_wparam(7, hovering),
+            mouse_lparam(110, 60),
+        );
+        send(
+            backend.platform(),
+            WM_POINTERUPDATE,
+            pointer_wparam(7, POINTER_MESSAGE_FLAG_PRIMARY),
+            mouse_lparam(110, 60),
+        );
+        backend.prepare_frame(&mut ctx).unwrap();
+        assert!(backend.pointers().pen().unwrap().released);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{CapturePolicy, CursorMap, MouseSource, PointerFrame};
+use imgui::MouseButton;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+use windows::Win32::Foundation::HWND;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MouseArea {
+    Client,
+    NonClient,
+}
+
+/// Input state that has to survive from one message of a window to the next.
+#[derive(Debug, Default)]
+pub(crate) struct WindowState {
+    pub(crate) high_surrogate: Option<u16>,
+    pub(crate) lead_byte: Option<u8>,
+    /// The area `TrackMouseEvent` is armed for, it reports the cursor leaving that area.
+    pub(crate) mouse_tracked_area: Option<MouseArea>,
+    /// The cursor left the client area and hasn't moved back in yet.
+    pub(crate) mouse_left: bool,
+    /// Device that sent the last mouse message.
+    pub(crate) mouse_source: MouseSource,
+    pub(crate) capture_policy: CapturePolicy,
+    /// The window took the mouse capture for a button press, and is the one to release it.
+    pub(crate) mouse_captured: bool,
+    /// `WM_POINTER*` messages are handled instead of left to `DefWindowProc`.
+    pub(crate) pointer_input: bool,
+    /// Contacts of the frame in progress.
+    pub(crate) pointers: PointerFrame,
+    /// The button pressed by the primary pointer.
+    pub(crate) pointer_button: Option<MouseButton>,
+    /// `WM_DEVICECHANGE` reported devices coming or going since the last frame.
+    pub(crate) devices_changed: bool,
+    /// DPI of the monitor the window is on, kept up to date by `WM_DPICHANGED`.
+    pub(crate) dpi: u32,
+    /// `WM_DPICHANGED` arrived since the last frame.
+    pub(crate) dpi_changed: bool,
+    /// `WM_DISPLAYCHANGE` arrived since the last frame, monitors may have come, gone or moved.
+    pub(crate) displays_changed: bool,
+    pub(crate) cursors: CursorMap,
+}
+
+pub(crate) type SharedWindowState = Rc<RefCell<WindowState>>;
+
+// Window messages are always delivered on the thread owning the window, so the state of every
+// window driven by a `Win32Impl` is looked up per thread.
+thread_local! {
+    static WINDOWS: RefCell<HashMap<isize, SharedWindowState>> = RefCell::new(HashMap::new());
+    // The window that saw the cursor last, the main one or any viewport's
+    static MOUSE_WINDOW: Cell<Option<HWND>> = const { Cell::new(None) };
+}
+
+pub(crate) fn register(hwnd: HWND) -> SharedWindowState {
+    let state = SharedWindowState::default();
+    WINDOWS.with(|windows| windows.borrow_mut().insert(hwnd.0, state.clone()));
+    state
+}
+
+pub(crate) fn unregister(hwnd: HWND, state: &SharedWindowState) {
+    WINDOWS.with(|windows| {
+        let mut windows = windows.borrow_mut();
+        if windows.get(&hwnd.0).is_some_and(|s| Rc::ptr_eq(s, state)) {
+            windows.remove(&hwnd.0);
+        }
+    });
+}
+
+pub(crate) fn lookup(hwnd: HWND) -> Option<SharedWindowState> {
+    WINDOWS.with(|windows| windows.borrow().get(&hwnd.0).cloned())
+}
+
+pub(crate) fn mouse_window() -> Option<HWND> {
+    MOUSE_WINDOW.with(Cell::get)
+}
+
+pub(crate) fn set_mouse_window(hwnd: Option<HWND>) {
+    MOUSE_WINDOW.with(|window| window.set(hwnd));
+}
@@ -0,0 +1,190 @@
+use crate::{
+    imgui_win32_window_proc_with_platform, NativePlatform, ProcResponse, Win32ImplError,
+    Win32Platform,
+};
+use std::{cell::Cell, rc::Rc};
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    UI::WindowsAndMessaging::WM_NCDESTROY,
+};
+
+/// Feeds the messages of a window to imgui ahead of its window procedure, made by
+/// `Win32Impl::subclass`.
+///
+/// Dropping it takes imgui out of the window procedure again.
+pub struct WindowSubclass<P: Win32Platform = NativePlatform> {
+    hwnd: HWND,
+    data: Box<SubclassData<P>>,
+}
+
+struct SubclassData<P> {
+    platform: Rc<P>,
+    swallow_input: Cell<bool>,
+}
+
+impl<P: Win32Platform> WindowSubclass<P> {
+    pub(crate) fn install(platform: Rc<P>, hwnd: HWND) -> Result<Self, Win32ImplError> {
+        let subclass = Self {
+            hwnd,
+            data: Box::new(SubclassData {
+                platform,
+                swallow_input: Cell::new(false),
+            }),
+        };
+        if !subclass.data.platform.set_window_subclass(
+            hwnd,
+            Some(subclass_proc::<P>),
+            subclass.id(),
+            subclass.id(),
+        ) {
+            return Err(Win32ImplError::ExternalError(format!(
+                "SetWindowSubclass failed for {hwnd:?}"
+            )));
+        }
+        Ok(subclass)
+    }
+
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    /// Keep mouse and keyboard messages from the window procedure while imgui wants them, the ones
+    /// answered `ProcResponse::Consumed`. Off by default, the window sees every input message.
+    pub fn set_swallow_input(&self, swallow: bool) {
+        self.data.swallow_input.set(swallow);
+    }
+
+    pub fn swallow_input(&self) -> bool {
+        self.data.swallow_input.get()
+    }
+
+    // The boxed data doesn't move, its address tells subclasses of the same window apart
+    fn id(&self) -> usize {
+        &*self.data as *const SubclassData<P> as usize
+    }
+}
+
+impl<P: Win32Platform> Drop for WindowSubclass<P> {
+    fn drop(&mut self) {
+        // Fails if the window is gone already, the subclass went with it
+        self.data
+            .platform
+            .remove_window_subclass(self.hwnd, Some(subclass_proc::<P>), self.id());
+    }
+}
+
+unsafe extern "system" fn subclass_proc<P: Win32Platform>(
+    hwnd: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+    id: usize,
+    data: usize,
+) -> LRESULT {
+    let data = &*(data as *const SubclassData<P>);
+    let platform = &*data.platform;
+
+    // A subclass has to be removed before its window is destroyed
+    if msg == WM_NCDESTROY {
+        platform.remove_window_subclass(hwnd, Some(subclass_proc::<P>), id);
+        return platform.def_subclass_proc(hwnd, msg, w_param, l_param);
+    }
+
+    let swallow = data.swallow_input.get();
+    match imgui_win32_window_proc_with_platform(platform, hwnd, msg, w_param, l_param) {
+        Ok(ProcResponse::Handled(result)) => return result,
+        Ok(ProcResponse::Consumed(result)) if swallow => return result.unwrap_or_default(),
+        _ => {}
+    }
+    platform.def_subclass_proc(hwnd, msg, w_param, l_param)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::*, MockPlatform, Win32ImplError};
+    use imgui::MouseButton;
+    use windows::Win32::{
+        System::SystemServices::MK_LBUTTON,
+        UI::WindowsAndMessaging::{
+            WM_CAPTURECHANGED, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEWHEEL, WM_SIZE, WM_XBUTTONUP,
+            XBUTTON1,
+        },
+    };
+
+    #[test]
+    fn subclass_feeds_imgui_ahead_of_the_window() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+        let subclass = unsafe { backend.subclass(HWND_MAIN) }.unwrap();
+        assert!(platform.is_subclassed(HWND_MAIN));
+
+        let wheel = |platform: &MockPlatform| unsafe {
+            platform.call_window_proc(
+                HWND_MAIN,
+                WM_MOUSEWHEEL,
+                WPARAM(wheel_wparam(120)),
+                LPARAM(0),
+            )
+        };
+        wheel(platform);
+        assert_eq!(platform.window_proc_messages(HWND_MAIN), [WM_MOUSEWHEEL]);
+        assert_eq!(ctx.new_frame().io().mouse_wheel, 1.0);
+        ctx.render();
+
+        ctx.io_mut().want_capture_mouse = true;
+        subclass.set_swallow_input(true);
+        wheel(platform);
+        unsafe { platform.call_window_proc(HWND_MAIN, WM_SIZE, WPARAM(0), LPARAM(0)) };
+        let side_button = WPARAM((XBUTTON1.0 << 16) as usize);
+        let result =
+            unsafe { platform.call_window_proc(HWND_MAIN, WM_XBUTTONUP, side_button, LPARAM(0)) };
+        assert_eq!(result, LRESULT(1));
+        assert_eq!(
+            platform.window_proc_messages(HWND_MAIN),
+            [WM_MOUSEWHEEL, WM_SIZE]
+        );
+        assert_eq!(ctx.new_frame().io().mouse_wheel, 1.0);
+
+        drop(subclass);
+        assert!(!platform.is_subclassed(HWND_MAIN));
+    }
+
+    #[test]
+    fn released_capture_comes_back_through_the_subclass() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+        let _subclass = unsafe { backend.subclass(HWND_MAIN) }.unwrap();
+        let click = |msg, w_param| unsafe {
+            platform.call_window_proc(HWND_MAIN, msg, WPARAM(w_param), LPARAM(0))
+        };
+
+        click(WM_LBUTTONDOWN, MK_LBUTTON.0 as usize);
+        assert!(ctx.new_frame().is_mouse_down(MouseButton::Left));
+        ctx.render();
+
+        // The button-up is done with the IO by the time WM_CAPTURECHANGED arrives
+        click(WM_LBUTTONUP, 0);
+        assert_eq!(platform.captured_window(), HWND(0));
+        assert_eq!(
+            platform.window_proc_messages(HWND_MAIN),
+            [WM_LBUTTONDOWN, WM_CAPTURECHANGED, WM_LBUTTONUP]
+        );
+        assert!(!ctx.new_frame().is_mouse_down(MouseButton::Left));
+    }
+
+    #[test]
+    fn subclass_is_removed_with_its_window() {
+        let (_guard, _ctx, backend) = setup();
+        let platform = backend.platform();
+        assert!(matches!(
+            unsafe { backend.subclass(HWND(0)) },
+            Err(Win32ImplError::InvalidWindow(HWND(0)))
+        ));
+
+        let _subclass = unsafe { backend.subclass(HWND_MAIN) }.unwrap();
+        unsafe { platform.call_window_proc(HWND_MAIN, WM_NCDESTROY, WPARAM(0), LPARAM(0)) };
+        assert!(!platform.is_subclassed(HWND_MAIN));
+        assert_eq!(platform.window_proc_messages(HWND_MAIN), [WM_NCDESTROY]);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    imgui_win32_window_proc_with_platform, MockPlatform, MonitorInfo, ProcResponse, Win32Impl,
+};
+use imgui::Context;
+use std::sync::{Mutex, MutexGuard};
+use windows::Win32::Foundation::{HWND, LPARAM, POINT, RECT, WPARAM};
+
+// imgui only supports a single active context per process.
+static CONTEXT_LOCK: Mutex<()> = Mutex::new(());
+
+pub(crate) const HWND_MAIN: HWND = HWND(0x1000);
+
+pub(crate) fn context() -> (MutexGuard<'static, ()>, Context) {
+    let guard = CONTEXT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut ctx = Context::create();
+    ctx.set_ini_filename(None);
+    ctx.fonts().build_rgba32_texture();
+    ctx.io_mut().display_size = [800.0, 600.0];
+    (guard, ctx)
+}
+
+/// An 800x600 client area at (100, 50) on screen, in the foreground.
+pub(crate) fn mock_platform() -> MockPlatform {
+    let platform = MockPlatform::new();
+    platform.set_client_rect(RECT {
+        left: 0,
+        top: 0,
+        right: 800,
+        bottom: 600,
+    });
+    platform.set_client_origin(POINT { x: 100, y: 50 });
+    platform.set_foreground_window(HWND_MAIN);
+    platform
+}
+
+/// A context with a backend for `HWND_MAIN`, which is dropped ahead of the context.
+pub(crate) fn setup() -> (MutexGuard<'static, ()>, Context, Win32Impl<MockPlatform>) {
+    setup_with(mock_platform())
+}
+
+pub(crate) fn setup_with(
+    platform: MockPlatform,
+) -> (MutexGuard<'static, ()>, Context, Win32Impl<MockPlatform>) {
+    let (guard, mut ctx) = context();
+    let backend = Win32Impl::init_with_platform(&mut ctx, HWND_MAIN, platform).unwrap();
+    (guard, ctx, backend)
+}
+
+// Queued input events are only applied to the IO state by `NewFrame`.
+pub(crate) fn send(
+    platform: &MockPlatform,
+    msg: u32,
+    w_param: usize,
+    l_param: isize,
+) -> ProcResponse {
+    unsafe {
+        imgui_win32_window_proc_with_platform(
+            platform,
+            HWND_MAIN,
+            msg,
+            WPARAM(w_param),
+            LPARAM(l_param),
+        )
+    }
+    .unwrap()
+}
+
+pub(crate) fn wheel_wparam(delta: i16) -> usize {
+    ((delta as u16 as u32) << 16) as usize
+}
+
+pub(crate) fn mouse_lparam(x: i16, y: i16) -> isize {
+    ((y as u16 as u32) << 16 | x as u16 as u32) as isize
+}
+
+// A 1080p monitor whose task bar takes the bottom 40 pixels
+pub(crate) fn monitor_at(left: i32, primary: bool) -> MonitorInfo {
+    let rect = |bottom| RECT {
+        left,
+        top: 0,
+        right: left + 1920,
+        bottom,
+    };
+    MonitorInfo {
+        rect: rect(1080),
+        work_rect: rect(1040),
+        dpi_scale: 1.0,
+        primary,
+    }
+}
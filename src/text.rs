@@ -0,0 +1,145 @@
+use crate::{state::WindowState, ProcResponse, Win32Platform};
+use imgui::Io;
+use windows::Win32::{
+    Foundation::{HWND, LRESULT},
+    UI::WindowsAndMessaging::UNICODE_NOCHAR,
+};
+
+const HIGH_SURROGATES: std::ops::RangeInclusive<u16> = 0xd800..=0xdbff;
+const LOW_SURROGATES: std::ops::RangeInclusive<u16> = 0xdc00..=0xdfff;
+
+fn add_utf16(io: &mut Io, units: &[u16]) {
+    for c in char::decode_utf16(units.iter().copied()).flatten() {
+        io.add_input_character(c);
+    }
+}
+
+/// Handles `WM_CHAR`, which carries a UTF-16 code unit for Unicode windows and a byte of the
+/// keyboard's code page for ANSI windows.
+pub(crate) fn handle_char<P: Win32Platform>(
+    platform: &P,
+    window: HWND,
+    state: &mut WindowState,
+    io: &mut Io,
+    w_param: u32,
+) {
+    if w_param == 0 || w_param >= 0x10000 {
+        return;
+    }
+
+    if !platform.is_window_unicode(window) {
+        let byte = w_param as u8;
+        let bytes = match state.lead_byte.take() {
+            Some(lead) => vec![lead, byte],
+            // Double-byte characters arrive as two messages, wait for the trail byte
+            None if platform.is_dbcs_lead_byte(byte) => {
+                state.lead_byte = Some(byte);
+                return;
+            }
+            None => vec![byte],
+        };
+        add_utf16(io, &platform.multi_byte_to_wide(&bytes));
+        return;
+    }
+
+    let unit = w_param as u16;
+    if HIGH_SURROGATES.contains(&unit) {
+        state.high_surrogate = Some(unit);
+    } else if LOW_SURROGATES.contains(&unit) {
+        // A low surrogate without its high half can't be decoded and is dropped
+        if let Some(high) = state.high_surrogate.take() {
+            add_utf16(io, &[high, unit]);
+        }
+    } else {
+        state.high_surrogate = None;
+        add_utf16(io, &[unit]);
+    }
+}
+
+/// Handles `WM_UNICHAR`, which carries a full UTF-32 code point.
+///
+/// A character imgui took is answered `FALSE`, passing it on would have `DefWindowProc` post the
+/// same character again as `WM_CHAR`.
+pub(crate) fn handle_unichar(io: &mut Io, w_param: u32) -> ProcResponse {
+    // Senders probe for WM_UNICHAR support with UNICODE_NOCHAR and expect TRUE back
+    if w_param == UNICODE_NOCHAR {
+        return ProcResponse::Handled(LRESULT(1));
+    }
+
+    match char::from_u32(w_param).filter(|&c| c != '\0') {
+        Some(c) => {
+            io.add_input_character(c);
+            ProcResponse::Handled(LRESULT(0))
+        }
+        None => ProcResponse::PassThrough,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::*, ProcResponse};
+    use imgui::Context;
+    use windows::Win32::UI::WindowsAndMessaging::{WM_CHAR, WM_UNICHAR};
+
+    fn typed_text(ctx: &mut Context) -> String {
+        ctx.new_frame().io().input_queue_characters().collect()
+    }
+
+    #[test]
+    fn surrogate_pairs_are_assembled() {
+        let (_guard, mut ctx, backend) = setup();
+
+        for unit in "a\u{1f600}".encode_utf16() {
+            send(backend.platform(), WM_CHAR, unit as usize, 0);
+        }
+        // A dangling high surrogate is dropped once a regular character follows
+        send(backend.platform(), WM_CHAR, 0xd83d, 0);
+        send(backend.platform(), WM_CHAR, 'b' as usize, 0);
+        // So is a low surrogate without its high half
+        send(backend.platform(), WM_CHAR, 0xde00, 0);
+
+        assert_eq!(typed_text(&mut ctx), "a\u{1f600}b");
+    }
+
+    #[test]
+    fn unichar_accepts_code_points_and_answers_probe() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        let probe = send(platform, WM_UNICHAR, UNICODE_NOCHAR as usize, 0);
+        assert_eq!(probe, ProcResponse::Handled(LRESULT(1)));
+        let response = send(platform, WM_UNICHAR, 0x1f600, 0);
+        assert_eq!(response, ProcResponse::Handled(LRESULT(0)));
+        send(platform, WM_UNICHAR, 'z' as usize, 0);
+
+        assert_eq!(typed_text(&mut ctx), "\u{1f600}z");
+    }
+
+    #[test]
+    fn unichar_is_not_typed_again_as_wm_char() {
+        let (_guard, mut ctx, backend) = setup();
+        let platform = backend.platform();
+
+        // What DefWindowProc does with a WM_UNICHAR it is passed
+        let default_proc = || {
+            send(platform, WM_CHAR, 'z' as usize, 0);
+            LRESULT(0)
+        };
+        send(platform, WM_UNICHAR, 'z' as usize, 0).into_lresult(default_proc);
+
+        assert_eq!(typed_text(&mut ctx), "z");
+    }
+
+    #[test]
+    fn ansi_windows_decode_code_page_bytes() {
+        let platform = mock_platform();
+        platform.set_ansi_window(true);
+        let (_guard, mut ctx, backend) = setup_with(platform);
+
+        send(backend.platform(), WM_CHAR, 0xe9, 0);
+        send(backend.platform(), WM_CHAR, 'x' as usize, 0);
+
+        assert_eq!(typed_text(&mut ctx), "\u{e9}x");
+    }
+}
@@ -0,0 +1,570 @@
+use crate::{
+    imgui_win32_window_proc_with_platform,
+    state::{self, SharedWindowState},
+    NativePlatform, ProcResponse, Win32Platform,
+};
+use imgui::{
+    internal::RawCast,
+    sys::{
+        igDestroyPlatformWindows, igFindViewportByID, igFindViewportByPlatformHandle,
+        igGetCurrentContext, ImGuiBackendFlags_HasMouseHoveredViewport, ImGuiContext,
+        ImGuiViewport, ImGuiViewportFlags_NoFocusOnClick, ImGuiViewportFlags_NoInputs,
+    },
+    BackendFlags, Context, PlatformViewportBackend, Viewport, ViewportFlags,
+};
+use std::{
+    ffi::c_void,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use windows::{
+    core::PCWSTR,
+    w,
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
+        UI::WindowsAndMessaging::*,
+    },
+};
+
+pub(crate) const CLASS_NAME: PCWSTR = w!("ImGui Platform");
+
+// Backends sharing `CLASS_NAME`, window classes belong to the whole process
+pub(crate) static CLASS_USERS: AtomicUsize = AtomicUsize::new(0);
+
+/// What the backend keeps in `Viewport::platform_user_data`.
+struct ViewportData {
+    hwnd: HWND,
+    /// The main viewport's window belongs to the application and is never destroyed.
+    owned: bool,
+    style: WINDOW_STYLE,
+    ex_style: WINDOW_EX_STYLE,
+    /// Input state of a secondary window, registered for as long as the window exists.
+    state: Option<SharedWindowState>,
+}
+
+/// imgui's platform interface, creating and driving a window for every secondary viewport.
+pub(crate) struct ViewportBackend<P: Win32Platform> {
+    platform: Rc<P>,
+    main_hwnd: HWND,
+}
+
+/// Sets the backend up for `ConfigFlags::VIEWPORTS_ENABLE` and hands `hwnd` to the main viewport,
+/// returns the context to pass to `shutdown`.
+pub(crate) unsafe fn init<P: Win32Platform>(
+    imgui: &mut Context,
+    hwnd: HWND,
+    platform: Rc<P>,
+) -> *mut ImGuiContext {
+    let io = imgui.io_mut();
+    io.backend_flags
+        .insert(BackendFlags::PLATFORM_HAS_VIEWPORTS);
+    // Hovered viewports are reported from `WindowFromPoint`, which skips `NoInputs` viewports
+    io.raw_mut().BackendFlags |= ImGuiBackendFlags_HasMouseHoveredViewport as i32;
+
+    if CLASS_USERS.fetch_add(1, Ordering::SeqCst) == 0 {
+        platform.register_viewport_class();
+    }
+
+    let main_viewport = imgui.main_viewport_mut();
+    main_viewport.platform_handle = hwnd.0 as *mut c_void;
+    main_viewport.platform_user_data = Box::into_raw(Box::new(ViewportData {
+        hwnd,
+        owned: false,
+        style: WINDOW_STYLE::default(),
+        ex_style: WINDOW_EX_STYLE::default(),
+        state: None,
+    })) as *mut c_void;
+
+    imgui.set_platform_backend(ViewportBackend {
+        platform,
+        main_hwnd: hwnd,
+    });
+    igGetCurrentContext()
+}
+
+/// Destroys the windows of secondary viewports if `context` is still the current one, and
+/// unregisters `CLASS_NAME` once no backend uses it anymore.
+///
+/// The windows of a context that isn't current are left to imgui, which destroys them along with
+/// the context.
+pub(crate) unsafe fn shutdown<P: Win32Platform>(platform: &P, context: *mut ImGuiContext) {
+    if igGetCurrentContext() == context {
+        igDestroyPlatformWindows();
+    }
+    if CLASS_USERS.fetch_sub(1, Ordering::SeqCst) == 1 {
+        platform.unregister_viewport_class();
+    }
+}
+
+/// Whether `hwnd` is the window of one of imgui's viewports.
+pub(crate) unsafe fn is_viewport_window(hwnd: HWND) -> bool {
+    !igFindViewportByPlatformHandle(hwnd.0 as *mut c_void).is_null()
+}
+
+/// ID of the viewport `hwnd` belongs to, `0` for windows outside of imgui.
+pub(crate) unsafe fn viewport_id(hwnd: HWND) -> u32 {
+    match igFindViewportByPlatformHandle(hwnd.0 as *mut c_void).as_ref() {
+        Some(viewport) => viewport.ID,
+        None => 0,
+    }
+}
+
+fn window_styles(flags: ViewportFlags) -> (WINDOW_STYLE, WINDOW_EX_STYLE) {
+    let style = match flags.contains(ViewportFlags::NO_DECORATION) {
+        true => WS_POPUP,
+        false => WS_OVERLAPPEDWINDOW,
+    };
+    let mut ex_style = match flags.contains(ViewportFlags::NO_TASK_BAR_ICON) {
+        true => WS_EX_TOOLWINDOW,
+        false => WS_EX_APPWINDOW,
+    };
+    if flags.contains(ViewportFlags::TOP_MOST) {
+        ex_style |= WS_EX_TOPMOST;
+    }
+    (style, ex_style)
+}
+
+fn client_rect(pos: [f32; 2], size: [f32; 2]) -> RECT {
+    RECT {
+        left: pos[0] as i32,
+        top: pos[1] as i32,
+        right: (pos[0] + size[0]) as i32,
+        bottom: (pos[1] + size[1]) as i32,
+    }
+}
+
+unsafe fn viewport_data<'a>(viewport: &Viewport) -> Option<&'a mut ViewportData> {
+    (viewport.platform_user_data as *mut ViewportData).as_mut()
+}
+
+// `None` for a viewport whose window couldn't be made
+fn viewport_hwnd(viewport: &Viewport) -> Option<HWND> {
+    unsafe { viewport_data(viewport) }.map(|data| data.hwnd)
+}
+
+// `ParentViewportId` isn't exposed by imgui-rs
+unsafe fn parent_hwnd(viewport: &mut Viewport) -> HWND {
+    let parent_id = (*(viewport as *mut Viewport as *mut ImGuiViewport)).ParentViewportId;
+    match parent_id {
+        0 => HWND(0),
+        id => match igFindViewportByID(id).as_ref() {
+            Some(parent) => HWND(parent.PlatformHandle as isize),
+            None => HWND(0),
+        },
+    }
+}
+
+impl<P: Win32Platform> PlatformViewportBackend for ViewportBackend<P> {
+    fn create_window(&mut self, viewport: &mut Viewport) {
+        let (style, ex_style) = window_styles(viewport.flags);
+        let parent = unsafe { parent_hwnd(viewport) };
+        let rect = self.platform.adjust_window_rect(
+            client_rect(viewport.pos, viewport.size),
+            style,
+            ex_style,
+        );
+        let hwnd = self
+            .platform
+            .create_window(CLASS_NAME, style, ex_style, rect, parent);
+        // The viewport is left without a window, the other callbacks skip it
+        if hwnd.0 == 0 {
+            return;
+        }
+
+        let data = ViewportData {
+            hwnd,
+            owned: true,
+            style,
+            ex_style,
+            state: Some(state::register(hwnd)),
+        };
+        viewport.platform_user_data = Box::into_raw(Box::new(data)) as *mut c_void;
+        viewport.platform_handle = hwnd.0 as *mut c_void;
+        viewport.platform_handle_raw = hwnd.0 as *mut c_void;
+        viewport.platform_request_resize = false;
+    }
+
+    fn destroy_window(&mut self, viewport: &mut Viewport) {
+        let data = viewport.platform_user_data as *mut ViewportData;
+        if !data.is_null() {
+            let data = unsafe { Box::from_raw(data) };
+            // Hand a capture held by the dying window back to the main window
+            if self.platform.capture() == data.hwnd {
+                self.platform.release_capture();
+                self.platform.set_capture(self.main_hwnd);
+            }
+            if let Some(state) = &data.state {
+                state::unregister(data.hwnd, state);
+            }
+            if data.owned {
+                self.platform.destroy_window(data.hwnd);
+            }
+        }
+        viewport.platform_user_data = std::ptr::null_mut();
+        viewport.platform_handle = std::ptr::null_mut();
+    }
+
+    fn show_window(&mut self, viewport: &mut Viewport) {
+        let cmd = match viewport
+            .flags
+            .contains(ViewportFlags::NO_FOCUS_ON_APPEARING)
+        {
+            true => SW_SHOWNA,
+            false => SW_SHOW,
+        };
+        if let Some(hwnd) = viewport_hwnd(viewport) {
+            self.platform.show_window(hwnd, cmd);
+        }
+    }
+
+    fn set_window_pos(&mut self, viewport: &mut Viewport, pos: [f32; 2]) {
+        if let Some(data) = unsafe { viewport_data(viewport) } {
+            let rect = self.platform.adjust_window_rect(
+                client_rect(pos, [0.0, 0.0]),
+                data.style,
+                data.ex_style,
+            );
+            let flags = SWP_NOZORDER | SWP_NOSIZE | SWP_NOACTIVATE;
+            self.platform
+                .set_window_pos(data.hwnd, HWND(0), rect, flags);
+        }
+    }
+
+    fn get_window_pos(&mut self, viewport: &mut Viewport) -> [f32; 2] {
+        let origin = viewport_hwnd(viewport)
+            .and_then(|hwnd| self.platform.client_to_screen(hwnd, POINT { x: 0, y: 0 }));
+        match origin {
+            Some(pos) => [pos.x as f32, pos.y as f32],
+            None => [0.0, 0.0],
+        }
+    }
+
+    fn set_window_size(&mut self, viewport: &mut Viewport, size: [f32; 2]) {
+        if let Some(data) = unsafe { viewport_data(viewport) } {
+            let rect = self.platform.adjust_window_rect(
+                client_rect([0.0, 0.0], size),
+                data.style,
+                data.ex_style,
+            );
+            let flags = SWP_NOZORDER | SWP_NOMOVE | SWP_NOACTIVATE;
+            self.platform
+                .set_window_pos(data.hwnd, HWND(0), rect, flags);
+        }
+    }
+
+    fn get_window_size(&mut self, viewport: &mut Viewport) -> [f32; 2] {
+        match viewport_hwnd(viewport).map(|hwnd| self.platform.client_rect(hwnd)) {
+            Some(Ok(rect)) => [
+                (rect.right - rect.left) as f32,
+                (rect.bottom - rect.top) as f32,
+            ],
+            _ => [0.0, 0.0],
+        }
+    }
+
+    fn set_window_focus(&mut self, viewport: &mut Viewport) {
+        if let Some(hwnd) = viewport_hwnd(viewport) {
+            self.platform.focus_window(hwnd);
+        }
+    }
+
+    fn get_window_focus(&mut self, viewport: &mut Viewport) -> bool {
+        viewport_hwnd(viewport) == Some(self.platform.foreground_window())
+    }
+
+    fn get_window_minimized(&mut self, viewport: &mut Viewport) -> bool {
+        viewport_hwnd(viewport).is_some_and(|hwnd| self.platform.is_minimized(hwnd))
+    }
+
+    fn set_window_title(&mut self, viewport: &mut Viewport, title: &str) {
+        if let Some(hwnd) = viewport_hwnd(viewport) {
+            self.platform.set_window_title(hwnd, title);
+        }
+    }
+
+    fn set_window_alpha(&mut self, viewport: &mut Viewport, alpha: f32) {
+        if let Some(hwnd) = viewport_hwnd(viewport) {
+            self.platform.set_window_alpha(hwnd, alpha);
+        }
+    }
+
+    fn update_window(&mut self, viewport: &mut Viewport) {
+        let data = match unsafe { viewport_data(viewport) } {
+            Some(data) if data.owned => data,
+            _ => return,
+        };
+
+        // Flags such as `NO_DECORATION` can change after the window was made
+        let (style, ex_style) = window_styles(viewport.flags);
+        if (style, ex_style) == (data.style, data.ex_style) {
+            return;
+        }
+
+        let top_most = ex_style.0 & WS_EX_TOPMOST.0 != 0;
+        let top_most_changed = (data.ex_style.0 & WS_EX_TOPMOST.0 != 0) != top_most;
+        let (insert_after, flags) = match top_most_changed {
+            true if top_most => (HWND_TOPMOST, SET_WINDOW_POS_FLAGS(0)),
+            true => (HWND_NOTOPMOST, SET_WINDOW_POS_FLAGS(0)),
+            false => (HWND(0), SWP_NOZORDER),
+        };
+        data.style = style;
+        data.ex_style = ex_style;
+        self.platform.set_window_style(data.hwnd, style, ex_style);
+
+        let rect = self.platform.adjust_window_rect(
+            client_rect(viewport.pos, viewport.size),
+            style,
+            ex_style,
+        );
+        let flags = flags | SWP_NOACTIVATE | SWP_FRAMECHANGED;
+        self.platform
+            .set_window_pos(data.hwnd, insert_after, rect, flags);
+        // A window whose style changed is hidden until shown again
+        self.platform.show_window(data.hwnd, SW_SHOWNA);
+        viewport.platform_request_move = true;
+        viewport.platform_request_resize = true;
+    }
+
+    // Rendering is up to the renderer backend
+    fn render_window(&mut self, _viewport: &mut Viewport) {}
+
+    fn swap_buffers(&mut self, _viewport: &mut Viewport) {}
+
+    fn create_vk_surface(
+        &mut self,
+        _viewport: &mut Viewport,
+        _instance: u64,
+        _out_surface: &mut u64,
+    ) -> i32 {
+        -1
+    }
+}
+
+/// Window procedure of the `CLASS_NAME` windows made for secondary viewports.
+pub(crate) unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    match handle_message(&NativePlatform, hwnd, msg, w_param, l_param) {
+        Some(result) => result,
+        None => DefWindowProcW(hwnd, msg, w_param, l_param),
+    }
+}
+
+/// Feeds the input of a secondary viewport's window to imgui and turns window management messages
+/// into requests on its viewport, `None` leaves the message to `DefWindowProc`.
+pub(crate) unsafe fn handle_message<P: Win32Platform>(
+    platform: &P,
+    hwnd: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> Option<LRESULT> {
+    if igGetCurrentContext().is_null() {
+        return None;
+    }
+
+    if let Ok(ProcResponse::Handled(result)) =
+        imgui_win32_window_proc_with_platform(platform, hwnd, msg, w_param, l_param)
+    {
+        return Some(result);
+    }
+
+    let viewport = igFindViewportByPlatformHandle(hwnd.0 as *mut c_void).as_mut()?;
+    match msg {
+        WM_CLOSE => {
+            viewport.PlatformRequestClose = true;
+            Some(LRESULT(0))
+        }
+        WM_MOVE => {
+            viewport.PlatformRequestMove = true;
+            None
+        }
+        WM_SIZE => {
+            viewport.PlatformRequestResize = true;
+            None
+        }
+        WM_MOUSEACTIVATE if viewport.Flags & ImGuiViewportFlags_NoFocusOnClick as i32 != 0 => {
+            Some(LRESULT(MA_NOACTIVATE as isize))
+        }
+        // Clicks on a `NO_INPUTS` viewport go through to the window underneath
+        WM_NCHITTEST if viewport.Flags & ImGuiViewportFlags_NoInputs as i32 != 0 => {
+            Some(LRESULT(HTTRANSPARENT as isize))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state, test_util::*, MockPlatform, Win32Impl};
+    use imgui::BackendFlags;
+    use std::sync::MutexGuard;
+    use windows::Win32::Foundation::RECT;
+
+    #[test]
+    fn styles_follow_viewport_flags() {
+        assert_eq!(
+            window_styles(ViewportFlags::empty()),
+            (WS_OVERLAPPEDWINDOW, WS_EX_APPWINDOW)
+        );
+        assert_eq!(
+            window_styles(
+                ViewportFlags::NO_DECORATION
+                    | ViewportFlags::NO_TASK_BAR_ICON
+                    | ViewportFlags::TOP_MOST
+            ),
+            (WS_POPUP, WS_EX_TOOLWINDOW | WS_EX_TOPMOST)
+        );
+    }
+
+    fn viewport_platform() -> MockPlatform {
+        let platform = mock_platform();
+        platform.set_monitors(vec![monitor_at(0, true)]);
+        platform
+    }
+
+    fn setup_viewports() -> (MutexGuard<'static, ()>, Context, Win32Impl<MockPlatform>) {
+        let (guard, mut ctx, backend) = setup_with(viewport_platform());
+        enable_viewports(&mut ctx);
+        (guard, ctx, backend)
+    }
+
+    fn enable_viewports(ctx: &mut Context) {
+        let io = ctx.io_mut();
+        io.config_flags.insert(imgui::ConfigFlags::VIEWPORTS_ENABLE);
+        // imgui only makes secondary viewports when a renderer can draw them
+        io.backend_flags
+            .insert(BackendFlags::RENDERER_HAS_VIEWPORTS);
+    }
+
+    // Runs a frame with a window outside of the main viewport, which is at (100, 50) on screen
+    fn open_tool_viewport(ctx: &mut Context, backend: &mut Win32Impl<MockPlatform>) -> HWND {
+        backend.prepare_frame(ctx).unwrap();
+        let ui = ctx.new_frame();
+        ui.window("Tool")
+            .position([1000.0, 100.0], imgui::Condition::Always)
+            .size([200.0, 100.0], imgui::Condition::Always)
+            .build(|| {});
+        ctx.render();
+        ctx.update_platform_windows();
+
+        let windows = backend.platform().windows();
+        assert_eq!(windows.len(), 1);
+        windows[0].0
+    }
+
+    #[test]
+    fn secondary_viewports_get_their_own_window() {
+        let (_guard, mut ctx, mut backend) = setup_viewports();
+
+        let hwnd = open_tool_viewport(&mut ctx, &mut backend);
+        let window = backend.platform().window(hwnd).unwrap();
+        assert_eq!(
+            window.rect,
+            RECT {
+                left: 1000,
+                top: 100,
+                right: 1200,
+                bottom: 200,
+            }
+        );
+        assert!(window.visible);
+        assert_eq!(window.title, "Tool");
+        assert_eq!(window.style, WS_POPUP);
+        assert!(state::lookup(hwnd).is_some());
+
+        let platform = backend.platform.clone();
+        drop(backend);
+        assert!(platform.windows().is_empty());
+        assert!(!platform.viewport_class_registered());
+        assert!(state::lookup(hwnd).is_none());
+    }
+
+    #[test]
+    fn dropping_a_backend_leaves_other_contexts_alone() {
+        let (_guard, mut first_ctx, mut first) = setup_viewports();
+        open_tool_viewport(&mut first_ctx, &mut first);
+        let first_ctx = first_ctx.suspend();
+
+        let mut second_ctx = Context::create();
+        second_ctx.set_ini_filename(None);
+        second_ctx.fonts().build_rgba32_texture();
+        second_ctx.io_mut().display_size = [800.0, 600.0];
+        enable_viewports(&mut second_ctx);
+        let mut second =
+            Win32Impl::init_with_platform(&mut second_ctx, HWND_MAIN, viewport_platform()).unwrap();
+        let hwnd = open_tool_viewport(&mut second_ctx, &mut second);
+        assert_eq!(CLASS_USERS.load(Ordering::SeqCst), 2);
+
+        let first_platform = first.platform.clone();
+        drop(first);
+        assert!(first_platform.viewport_class_registered());
+        assert_eq!(CLASS_USERS.load(Ordering::SeqCst), 1);
+        assert!(second.platform().window(hwnd).is_some());
+        assert!(state::lookup(hwnd).is_some());
+
+        drop(second);
+        assert_eq!(CLASS_USERS.load(Ordering::SeqCst), 0);
+        drop(second_ctx);
+        drop(first_ctx);
+    }
+
+    #[test]
+    fn failed_viewport_windows_are_skipped() {
+        let (_guard, mut ctx, mut backend) = setup_viewports();
+        backend.platform().set_window_creation_fails(true);
+
+        backend.prepare_frame(&mut ctx).unwrap();
+        let ui = ctx.new_frame();
+        ui.window("Tool")
+            .position([1000.0, 100.0], imgui::Condition::Always)
+            .size([200.0, 100.0], imgui::Condition::Always)
+            .build(|| {});
+        ctx.render();
+        ctx.update_platform_windows();
+
+        assert!(backend.platform().windows().is_empty());
+        assert!(state::lookup(HWND(0)).is_none());
+        assert!(ctx.viewports().last().unwrap().platform_handle.is_null());
+    }
+
+    #[test]
+    fn viewport_window_messages_become_requests() {
+        let (_guard, mut ctx, mut backend) = setup_viewports();
+        let hwnd = open_tool_viewport(&mut ctx, &mut backend);
+
+        let handle =
+            |msg| unsafe { handle_message(backend.platform(), hwnd, msg, WPARAM(0), LPARAM(0)) };
+        assert_eq!(handle(WM_CLOSE), Some(LRESULT(0)));
+        assert_eq!(handle(WM_MOVE), None);
+        assert_eq!(handle(WM_SIZE), None);
+
+        let viewport = unsafe {
+            &*imgui::sys::igFindViewportByPlatformHandle(hwnd.0 as *mut std::ffi::c_void)
+        };
+        assert!(viewport.PlatformRequestClose);
+        assert!(viewport.PlatformRequestMove);
+        assert!(viewport.PlatformRequestResize);
+    }
+
+    #[test]
+    fn viewport_mouse_positions_are_in_screen_coordinates() {
+        let (_guard, mut ctx, mut backend) = setup_viewports();
+
+        send(backend.platform(), WM_MOUSEMOVE, 0, mouse_lparam(10, 20));
+        backend.prepare_frame(&mut ctx).unwrap();
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [110.0, 70.0]);
+        ctx.render();
+        ctx.update_platform_windows();
+
+        send(backend.platform(), WM_NCMOUSEMOVE, 0, mouse_lparam(95, 45));
+        backend.prepare_frame(&mut ctx).unwrap();
+        let ui = ctx.new_frame();
+        assert_eq!(ui.io().mouse_pos, [95.0, 45.0]);
+    }
+}